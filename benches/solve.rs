@@ -0,0 +1,113 @@
+//! solving benchmark, run it with `cargo bench --bench solve`
+//!
+//! every chain starts straight and chases a target that moves every frame
+//! so that each solve runs all of its iterations
+//!
+//! to compare with an older version, check it out and run this same file against it
+//! the results of the switch to reusable buffers are in the README
+
+use bevy::prelude::*;
+use bevy_2d_inverse_kinematics::{map_new_ik, solve_ik, IKConstraint, IKTarget, JointConstraint};
+use std::{
+    f32::consts::PI,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const FRAMES: u32 = 200;
+const BONE_LENGTH: f32 = 10.;
+
+#[derive(Resource, Default)]
+struct Frame(u32);
+
+/// position of the anchor of a chain, which the target moves around
+#[derive(Component)]
+struct Origin(Vec2);
+
+fn spawn_chains(world: &mut World, chains: usize, joints: usize, nested: bool) {
+    for c in 0..chains {
+        let origin = Vec3::new(0., c as f32 * BONE_LENGTH * 2., 0.);
+        let mut entities = Vec::with_capacity(joints);
+
+        for i in 0..joints {
+            let global = origin + Vec3::X * BONE_LENGTH * i as f32;
+            let entity = match entities.last() {
+                Some(&parent) if nested => world
+                    .spawn((
+                        Transform::from_xyz(BONE_LENGTH, 0., 0.),
+                        GlobalTransform::from_translation(global),
+                        ChildOf(parent),
+                    ))
+                    .id(),
+                _ => world
+                    .spawn((
+                        Transform::from_translation(global),
+                        GlobalTransform::from_translation(global),
+                    ))
+                    .id(),
+            };
+            entities.push(entity);
+        }
+
+        let constraints = entities
+            .iter()
+            .map(|&e| (e, JointConstraint::new(PI / 4., PI / 4.)))
+            .collect();
+
+        world.entity_mut(*entities.last().unwrap()).insert((
+            IKConstraint::new(entities)
+                .with_iterations(10)
+                .with_epsilon(0.001)
+                .with_joint_constraints(constraints),
+            Origin(origin.xy()),
+        ));
+    }
+}
+
+fn move_targets(mut frame: ResMut<Frame>, mut query: Query<(&mut IKConstraint, &Origin)>) {
+    frame.0 += 1;
+    let t = frame.0 as f32 * 0.1;
+
+    for (mut ik, origin) in &mut query {
+        let anchor_offset = Vec2::new(ik.chain.len() as f32 * BONE_LENGTH * 0.6, 0.);
+        let pos = origin.0 + anchor_offset + Vec2::from_angle(t) * BONE_LENGTH * 2.;
+        ik.set_target(IKTarget::Pos(pos));
+    }
+}
+
+fn bench(name: &str, chains: usize, joints: usize, nested: bool) {
+    let mut world = World::new();
+    world.init_resource::<Frame>();
+    spawn_chains(&mut world, chains, joints, nested);
+
+    let mut setup = Schedule::default();
+    setup.add_systems(map_new_ik);
+    setup.run(&mut world);
+
+    let mut targets = Schedule::default();
+    targets.add_systems(move_targets);
+
+    let mut solve = Schedule::default();
+    solve.add_systems(solve_ik);
+
+    let mut total = Duration::ZERO;
+    for _ in 0..FRAMES {
+        targets.run(&mut world);
+
+        let start = Instant::now();
+        solve.run(&mut world);
+        total += black_box(start.elapsed());
+    }
+
+    println!(
+        "{name:<40} {chains:>5} chains x {joints:>3} joints: {:>10.3?} / frame",
+        total / FRAMES
+    );
+}
+
+fn main() {
+    bench("long chains", 10, 100, false);
+    bench("long nested chains", 10, 100, true);
+    bench("many chains", 1000, 4, false);
+    bench("many nested chains", 1000, 4, true);
+}
@@ -16,7 +16,7 @@ fn main() {
         .add_plugins(MeshPickingPlugin)
         .add_plugins(EguiPlugin::default())
         .add_plugins(WorldInspectorPlugin::new())
-        .add_plugins(IKPlugin)
+        .add_plugins(IKPlugin::default())
         .add_plugins(FrogPlugin)
         .add_systems(Startup, setup)
         .add_systems(Update, zoom)
@@ -0,0 +1,89 @@
+use bevy::{
+    animation::{
+        animation_curves::{AnimatableProperty, EvaluatorId},
+        AnimationEntityMut, AnimationEvaluationError,
+    },
+    prelude::*,
+};
+use std::any::TypeId;
+
+use crate::{IKConstraint, IKTarget};
+
+/// position of the target of an `IKConstraint`, to animate it with an `AnimatableCurve`
+/// the target must be an `IKTarget::Pos` or an `IKTarget::PosRot`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IKTargetPosProperty;
+
+impl AnimatableProperty for IKTargetPosProperty {
+    type Property = Vec2;
+
+    fn get_mut<'a>(
+        &self,
+        entity: &'a mut AnimationEntityMut,
+    ) -> Result<&'a mut Vec2, AnimationEvaluationError> {
+        match &mut constraint(entity)?.target {
+            IKTarget::Pos(pos) | IKTarget::PosRot(pos, _) => Ok(pos),
+            _ => Err(AnimationEvaluationError::PropertyNotPresent(TypeId::of::<
+                Self,
+            >(
+            ))),
+        }
+    }
+
+    fn evaluator_id(&self) -> EvaluatorId<'_> {
+        EvaluatorId::Type(TypeId::of::<Self>())
+    }
+}
+
+/// local offset of the target of an `IKConstraint`, to animate it with an `AnimatableCurve`
+/// the target must be an `IKTarget::EntityOffset`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IKTargetOffsetProperty;
+
+impl AnimatableProperty for IKTargetOffsetProperty {
+    type Property = Vec2;
+
+    fn get_mut<'a>(
+        &self,
+        entity: &'a mut AnimationEntityMut,
+    ) -> Result<&'a mut Vec2, AnimationEvaluationError> {
+        match &mut constraint(entity)?.target {
+            IKTarget::EntityOffset { offset, .. } => Ok(offset),
+            _ => Err(AnimationEvaluationError::PropertyNotPresent(TypeId::of::<
+                Self,
+            >(
+            ))),
+        }
+    }
+
+    fn evaluator_id(&self) -> EvaluatorId<'_> {
+        EvaluatorId::Type(TypeId::of::<Self>())
+    }
+}
+
+/// weight of an `IKConstraint`, to fade it in and out with an `AnimatableCurve`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IKWeightProperty;
+
+impl AnimatableProperty for IKWeightProperty {
+    type Property = f32;
+
+    fn get_mut<'a>(
+        &self,
+        entity: &'a mut AnimationEntityMut,
+    ) -> Result<&'a mut f32, AnimationEvaluationError> {
+        Ok(&mut constraint(entity)?.weight)
+    }
+
+    fn evaluator_id(&self) -> EvaluatorId<'_> {
+        EvaluatorId::Type(TypeId::of::<Self>())
+    }
+}
+
+fn constraint<'a>(
+    entity: &'a mut AnimationEntityMut,
+) -> Result<&'a mut IKConstraint, AnimationEvaluationError> {
+    entity.get_mut::<IKConstraint>().map(Mut::into_inner).ok_or(
+        AnimationEvaluationError::ComponentNotPresent(TypeId::of::<IKConstraint>()),
+    )
+}
@@ -0,0 +1,240 @@
+use bevy::{
+    animation::{animated_field, AnimationTarget},
+    prelude::*,
+};
+
+use crate::IKConstraint;
+
+/// add this component next to an `IKConstraint` to record the transforms of its chain while it's solved
+/// once the recording is over, they are baked into an `AnimationClip`
+/// with translation and rotation curves keyed by the `AnimationTarget`s of the chain entities
+///
+/// so that procedural motion can be played back without solving it again
+///
+/// the recording ends early if the chain is edited or one of its entities goes missing
+#[derive(Component, Debug)]
+pub struct IKRecording {
+    /// time at which the recording starts, in seconds of `Time::elapsed_secs`
+    pub start: f32,
+
+    /// time at which the recording ends, in seconds of `Time::elapsed_secs`
+    pub end: f32,
+
+    /// time of each sample, relative to `start`
+    times: Vec<f32>,
+
+    /// local transforms of each entity of the chain, one per sample
+    samples: Vec<Vec<Transform>>,
+
+    /// chain being recorded, as it was on the first sample
+    chain: Vec<Entity>,
+
+    clip: Option<Handle<AnimationClip>>,
+}
+
+impl IKRecording {
+    pub fn new(start: f32, end: f32) -> Self {
+        Self {
+            start,
+            end,
+            times: Vec::new(),
+            samples: Vec::new(),
+            chain: Vec::new(),
+            clip: None,
+        }
+    }
+
+    /// the baked clip, once the recording is over
+    pub fn clip(&self) -> Option<&Handle<AnimationClip>> {
+        self.clip.as_ref()
+    }
+
+    /// take a sample of the local transforms of the chain
+    /// fails if an entity is missing
+    fn sample(&mut self, time: f32, transforms: &Query<&Transform>) -> Result<(), Entity> {
+        let sample = self
+            .chain
+            .iter()
+            .map(|&e| transforms.get(e).copied().map_err(|_| e))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.times.push(time);
+        self.samples.resize_with(self.chain.len(), Vec::new);
+        for (samples, transform) in self.samples.iter_mut().zip(sample) {
+            samples.push(transform);
+        }
+        Ok(())
+    }
+
+    /// bake the samples into a clip
+    /// chain entities without an `AnimationTarget` are skipped
+    fn bake(&self, targets: &Query<&AnimationTarget>) -> AnimationClip {
+        let mut clip = AnimationClip::default();
+
+        for (e, samples) in self.chain.iter().zip(&self.samples) {
+            let Ok(target) = targets.get(*e) else {
+                warn!(
+                    "IK chain entity {} has no AnimationTarget, it won't be baked",
+                    e
+                );
+                continue;
+            };
+
+            let translations = self
+                .times
+                .iter()
+                .copied()
+                .zip(samples.iter().map(|t| t.translation));
+            let rotations = self
+                .times
+                .iter()
+                .copied()
+                .zip(samples.iter().map(|t| t.rotation));
+
+            if let (Ok(translations), Ok(rotations)) = (
+                UnevenSampleAutoCurve::new(translations),
+                UnevenSampleAutoCurve::new(rotations),
+            ) {
+                clip.add_curve_to_target(
+                    target.id,
+                    AnimatableCurve::new(animated_field!(Transform::translation), translations),
+                );
+                clip.add_curve_to_target(
+                    target.id,
+                    AnimatableCurve::new(animated_field!(Transform::rotation), rotations),
+                );
+            }
+        }
+
+        clip
+    }
+}
+
+/// sample the chains being recorded, after they are solved
+/// and bake the recordings that are over
+pub fn record_ik(
+    mut recordings: Query<(&IKConstraint, &mut IKRecording)>,
+    transforms: Query<&Transform>,
+    targets: Query<&AnimationTarget>,
+    time: Option<Res<Time>>,
+    clips: Option<ResMut<Assets<AnimationClip>>>,
+) {
+    let (Some(time), Some(mut clips)) = (time, clips) else {
+        return;
+    };
+    let now = time.elapsed_secs();
+
+    for (constraint, mut recording) in &mut recordings {
+        if recording.clip.is_some() || now < recording.start {
+            continue;
+        }
+
+        if recording.times.is_empty() {
+            recording.chain.clone_from(&constraint.chain);
+        }
+
+        let mut over = now >= recording.end;
+        if recording.chain != constraint.chain {
+            warn!("IK chain was edited while being recorded, the recording ends early");
+            over = true;
+        } else if now <= recording.end {
+            let t = now - recording.start;
+            if let Err(e) = recording.sample(t, &transforms) {
+                warn!("IK chain entity {} is missing, the recording ends early", e);
+                over = true;
+            }
+        }
+
+        if over {
+            if recording.times.len() < 2 {
+                warn!("IK recording is over with less than 2 samples, nothing to bake");
+            }
+
+            let clip = recording.bake(&targets);
+            recording.clip = Some(clips.add(clip));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IKPlugin, IKTarget};
+    use bevy::{animation::AnimationTargetId, time::TimeUpdateStrategy};
+    use std::time::Duration;
+
+    /// app stepping 0.1s per update, with a recorded 3 entities chain
+    fn recorded_chain(start: f32, end: f32) -> (App, Entity, Vec<AnimationTargetId>) {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            IKPlugin::default().with_debug(false),
+        ))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            100,
+        )))
+        .init_resource::<Assets<AnimationClip>>();
+
+        let world = app.world_mut();
+        let player = world.spawn_empty().id();
+        let ids = ["anchor", "joint", "effector"]
+            .map(|name| AnimationTargetId::from_name(&Name::new(name)));
+        let chain = ids
+            .iter()
+            .enumerate()
+            .map(|(i, &id)| {
+                world
+                    .spawn((
+                        Transform::from_xyz(10. * i as f32, 0., 0.),
+                        AnimationTarget { id, player },
+                    ))
+                    .id()
+            })
+            .collect::<Vec<_>>();
+
+        let effector = chain[2];
+        world.entity_mut(effector).insert((
+            IKConstraint::new(chain).with_target(IKTarget::Pos(Vec2::new(10., 10.))),
+            IKRecording::new(start, end),
+        ));
+
+        (app, effector, ids.to_vec())
+    }
+
+    fn clip(app: &App, effector: Entity) -> Option<&AnimationClip> {
+        let handle = app.world().get::<IKRecording>(effector)?.clip()?;
+        app.world().resource::<Assets<AnimationClip>>().get(handle)
+    }
+
+    #[test]
+    fn bakes_translation_and_rotation_curves() {
+        let (mut app, effector, ids) = recorded_chain(0., 0.35);
+
+        for _ in 0..4 {
+            app.update();
+        }
+        assert!(clip(&app, effector).is_none());
+
+        app.update();
+        let clip = clip(&app, effector).unwrap();
+        for id in ids {
+            assert_eq!(clip.curves_for_target(id).map(Vec::len), Some(2));
+        }
+    }
+
+    #[test]
+    fn ends_early_when_chain_is_edited() {
+        let (mut app, effector, _) = recorded_chain(0., 10.);
+
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(clip(&app, effector).is_none());
+
+        let mut constraint = app.world_mut().get_mut::<IKConstraint>(effector).unwrap();
+        constraint.chain.reverse();
+        app.update();
+        assert!(clip(&app, effector).is_some());
+    }
+}
@@ -0,0 +1,578 @@
+use bevy::{math::Vec2, reflect::Reflect};
+
+use crate::{IKReach, IKSolver, JointConstraint, OutOfReachMode, ReachFallback};
+
+/// an IK chain described with plain data, without any ECS
+/// this is what `IKSolver`s work on, so it can be used outside of a bevy `World` (servers, tests, tools...)
+///
+/// every slice is indexed from the anchor (0) to the effector
+#[derive(Clone, Copy, Debug)]
+pub struct IKChain<'a> {
+    /// length of each bone
+    /// `lengths[i]` is the distance between joint `i` and joint `i + 1`
+    pub lengths: &'a [f32],
+
+    /// absolute angle of each joint at rest
+    /// the one of the anchor is the reference of its `JointConstraint`
+    pub rest_angles: &'a [f32],
+
+    /// angle constraint of each joint, relative to the previous bone
+    pub constraints: &'a [Option<JointConstraint>],
+
+    /// max number of iterations to solve the chain
+    pub iterations: usize,
+
+    /// distance to the target under which the chain is considered solved
+    pub epsilon: f32,
+
+    /// wether the anchor can be pulled by the effector
+    pub anchor: AnchorMode,
+
+    /// absolute position of the anchor at rest
+    /// a floating anchor is pulled back towards it
+    pub anchor_rest: Vec2,
+
+    /// absolute position the middle joints bend towards (ie: in front of a knee)
+    /// without it, the chain bends the way it was bent on the previous solve
+    pub pole: Option<Vec2>,
+
+    /// absolute angle the effector should have on the target (ie: a hand gripping a handle)
+    /// without it, the effector looks at the target
+    pub target_angle: Option<f32>,
+
+    /// how much the last bone rotates so that the effector's joint constraint allows `target_angle`
+    /// between 0 (only the effector rotates) and 1 (the orientation is reached whenever possible)
+    pub orientation_weight: f32,
+
+    /// distance from the max reach under which the effector starts lagging behind the target
+    /// so that the chain eases into full extension instead of snapping straight (see `soft_target`)
+    /// a floating anchor still follows the target itself, only the reach around its new position is softened
+    /// 0 disables it
+    pub softness: f32,
+}
+
+/// how the anchor of a chain reacts to the effector being pulled further than the chain can reach
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect)]
+pub enum AnchorMode {
+    /// the anchor never moves
+    #[default]
+    Fixed,
+    /// the anchor follows the chain when it can't reach its target (ie: a torso pulled by a hand)
+    Floating {
+        /// how much of the distance between the target and the max reach the anchor doesn't follow
+        /// between 0 (the effector reaches the target) and 1 (doesn't move from its rest position)
+        stiffness: f32,
+        /// max distance between the anchor and its rest position
+        max_displacement: Option<f32>,
+    },
+}
+
+impl IKChain<'_> {
+    /// number of joints in the chain
+    pub fn len(&self) -> usize {
+        self.rest_angles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rest_angles.is_empty()
+    }
+
+    /// absolute direction of the anchor at rest
+    pub fn anchor_dir(&self) -> Vec2 {
+        Vec2::from_angle(self.rest_angles[0])
+    }
+
+    /// pose of the chain at rest, with the anchor at `anchor`
+    /// the rest angle of a joint after the anchor is the direction of the bone before it
+    pub fn rest_pose(&self, anchor: Vec2) -> IKPose {
+        let mut pose = IKPose::from_angles(anchor, self.rest_angles[1..].to_vec(), self.lengths);
+        pose.angles.clear();
+        pose.angles.extend_from_slice(self.rest_angles);
+        pose
+    }
+
+    /// clamp the angle of joint `i` (relative to the previous bone) to its `JointConstraint`
+    pub fn constrain(&self, i: usize, angle: f32) -> f32 {
+        match self.constraints[i] {
+            Some(JointConstraint { ccw, cw }) => angle.clamp(-cw, ccw),
+            None => angle,
+        }
+    }
+
+    /// wether a joint of `pose` is at one of the limits of its `JointConstraint`
+    /// `anchor_dir` is the reference direction of the anchor's constraint (see `anchor_dir`)
+    pub fn at_limit(&self, pose: &IKPose, anchor_dir: Vec2) -> bool {
+        let mut prev_dir = anchor_dir;
+        self.constraints.iter().enumerate().any(|(i, c)| {
+            let dir = Vec2::from_angle(pose.angles[i]);
+            let angle = prev_dir.angle_to(dir);
+            prev_dir = dir;
+            c.is_some_and(|JointConstraint { ccw, cw }| {
+                (angle - ccw).abs() < LIMIT_EPSILON || (angle + cw).abs() < LIMIT_EPSILON
+            })
+        })
+    }
+
+    /// region the effector can reach with the anchor at `anchor`
+    /// `anchor_dir` is the reference direction of the anchor's constraint (see `anchor_dir`)
+    pub fn reach(&self, anchor: Vec2, anchor_dir: Vec2) -> IKReach {
+        IKReach::new(anchor, anchor_dir, self.lengths, self.constraints)
+    }
+
+    /// absolute direction of the effector
+    /// it takes `target_angle`, or looks at the target, within its own joint constraint
+    /// `prev_dir` is the absolute direction of the last bone
+    pub(crate) fn effector_dir(&self, prev_dir: Vec2, effector: Vec2, target: Vec2) -> Vec2 {
+        let dir = match self.target_angle {
+            Some(angle) => Vec2::from_angle(angle),
+            None => (target - effector).try_normalize().unwrap_or(prev_dir),
+        };
+        let angle = self.constrain(self.len() - 1, prev_dir.angle_to(dir));
+        Vec2::from_angle(angle).rotate(prev_dir)
+    }
+
+    /// absolute direction of the last bone, rotated from `dir` towards the closest one
+    /// from which the effector's joint constraint allows `target_angle`, according to `orientation_weight`
+    pub fn oriented_bone_dir(&self, dir: Vec2) -> Vec2 {
+        let Some(target_angle) = self.target_angle else {
+            return dir;
+        };
+
+        let target_dir = Vec2::from_angle(target_angle);
+        let angle = target_dir.angle_to(dir);
+        let allowed = match self.constraints[self.len() - 1] {
+            Some(JointConstraint { ccw, cw }) => angle.clamp(-ccw, cw),
+            None => angle,
+        };
+
+        let angle = angle + (allowed - angle) * self.orientation_weight.clamp(0., 1.);
+        Vec2::from_angle(angle).rotate(target_dir)
+    }
+
+    /// target the effector goes to, with the anchor at `anchor`
+    /// within `softness` of the max reach, the distance to the anchor is eased out exponentially
+    /// so that the target is only reached at infinity
+    pub fn soft_target(&self, anchor: Vec2, target: Vec2) -> Vec2 {
+        if self.softness <= 0. {
+            return target;
+        }
+
+        let max = self.reach(anchor, self.anchor_dir()).max;
+        let softness = self.softness.min(max);
+        let hard = max - softness;
+
+        let to_target = target - anchor;
+        let dist = to_target.length();
+        if dist <= hard {
+            return target;
+        }
+
+        let soft = hard + softness * (1. - (-(dist - hard) / softness).exp());
+        anchor + to_target * (soft / dist)
+    }
+
+    /// wether the effector is within `epsilon` of the target
+    /// and the last bone doesn't have to move more than `epsilon` for the effector to reach `target_angle`
+    pub fn solved(&self, positions: &[Vec2], target: Vec2) -> bool {
+        let last = positions.len() - 1;
+        if positions[last].distance_squared(target) >= self.epsilon * self.epsilon {
+            return false;
+        }
+
+        let bone = positions[last] - positions[last - 1];
+        let oriented = self.oriented_bone_dir(bone.normalize()) * self.lengths[last - 1];
+        bone.distance_squared(oriented) < self.epsilon * self.epsilon
+    }
+
+    /// position of the anchor for the chain to solve towards `target`
+    /// a floating anchor is pulled from `anchor_rest` by the part of the target's distance beyond the max reach
+    /// `current` is returned for a fixed anchor
+    pub fn anchor_pos(&self, current: Vec2, target: Vec2) -> Vec2 {
+        match self.anchor {
+            AnchorMode::Fixed => current,
+            AnchorMode::Floating {
+                stiffness,
+                max_displacement,
+            } => {
+                let to_target = target - self.anchor_rest;
+                let max = self.reach(self.anchor_rest, self.anchor_dir()).max;
+                let beyond = (to_target.length() - max).max(0.);
+
+                let offset =
+                    to_target.normalize_or_zero() * beyond * (1. - stiffness.clamp(0., 1.));
+                match max_displacement {
+                    Some(max) => self.anchor_rest + offset.clamp_length_max(max),
+                    None => self.anchor_rest + offset,
+                }
+            }
+        }
+    }
+
+    /// move a pose as a whole, so that its anchor is at `anchor_pos`
+    /// solvers call it before solving, and then keep the anchor where it is
+    pub fn float_anchor(&self, pose: &mut IKPose, target: Vec2) {
+        if self.anchor == AnchorMode::Fixed {
+            return;
+        }
+
+        let offset = self.anchor_pos(pose.positions[0], target) - pose.positions[0];
+        for pos in pose.positions.iter_mut() {
+            *pos += offset;
+        }
+    }
+
+    /// float the anchor of `pose` towards `target`, and return the chain with its anchor fixed there
+    /// so that a softened target doesn't pull the anchor back within reach
+    pub(crate) fn floated(&self, pose: &mut IKPose, target: Vec2) -> Self {
+        self.float_anchor(pose, target);
+        Self {
+            anchor: AnchorMode::Fixed,
+            ..*self
+        }
+    }
+
+    /// mirror the middle joints that are on the wrong side of their neighbours towards the pole
+    /// and slightly bend the straight ones towards it, so that the chain doesn't flip once it went straight
+    /// bones lengths are kept, so solvers can call it on the starting pose
+    pub fn bend_to_pole(&self, positions: &mut [Vec2]) {
+        let Some(pole) = self.pole else {
+            return;
+        };
+
+        for i in 1..positions.len() - 1 {
+            let prev = positions[i - 1];
+            let line = positions[i + 1] - prev;
+            let pole_side = line.perp_dot(pole - prev);
+            let joint_side = line.perp_dot(positions[i] - prev);
+
+            // a folded bone or a pole right on the line doesn't tell any side
+            if line.length_squared() < f32::EPSILON || pole_side == 0. {
+                continue;
+            }
+
+            if joint_side.abs() < 1e-4 * line.length_squared() {
+                // rotate the bone towards the pole, and the rest of the chain back the other way
+                let angle = POLE_NUDGE.copysign(pole_side);
+                rotate_around(&mut positions[i..], prev, angle);
+                let joint = positions[i];
+                rotate_around(&mut positions[i + 1..], joint, -2. * angle);
+            } else if joint_side.signum() != pole_side.signum() {
+                let projected =
+                    prev + line * (positions[i] - prev).dot(line) / line.length_squared();
+                positions[i] = 2. * projected - positions[i];
+            }
+        }
+    }
+
+    /// solve the chain from `pose` towards `target` and return the solved pose
+    /// an out of reach target is stretched towards (see `solve_with_fallback` for the other behaviors)
+    pub fn solve(&self, solver: &dyn IKSolver, mut pose: IKPose, target: Vec2) -> IKPose {
+        let mut fallback = ReachFallback::default();
+        self.solve_with_fallback(
+            solver,
+            &mut pose,
+            target,
+            OutOfReachMode::Stretch,
+            &mut fallback,
+            0.,
+        );
+        pose
+    }
+
+    /// solve `pose` in place towards `target`, and handle an out of reach target according to `mode`
+    /// `fallback` is the state of `mode`, to keep between the solves of the chain
+    /// and `dt` the time since the last solve, for the transition of `mode`
+    /// returns the number of iterations used
+    pub fn solve_with_fallback(
+        &self,
+        solver: &dyn IKSolver,
+        pose: &mut IKPose,
+        target: Vec2,
+        mode: OutOfReachMode,
+        fallback: &mut ReachFallback,
+        dt: f32,
+    ) -> usize {
+        debug_assert_eq!(self.lengths.len() + 1, self.len());
+        debug_assert_eq!(self.constraints.len(), self.len());
+        debug_assert_eq!(pose.positions.len(), self.len());
+
+        let chain = self.floated(pose, target);
+        if mode == OutOfReachMode::Stretch {
+            let target = chain.soft_target(pose.positions[0], target);
+            return solver.solve(&chain, pose, target);
+        }
+
+        let reach = chain.reach(pose.positions[0], chain.anchor_dir());
+        let in_reach = reach.contains(target);
+        fallback.update(mode, !in_reach, dt);
+
+        let solve_target = fallback.target(mode, &reach, target);
+        let solve_target = chain.soft_target(pose.positions[0], solve_target);
+        let iterations = solver.solve(&chain, pose, solve_target);
+        if in_reach {
+            fallback.store(&chain, pose);
+        }
+        fallback.apply(mode, &chain, pose);
+        iterations
+    }
+}
+
+/// angle under which a joint is considered at the limit of its constraint, in radians
+const LIMIT_EPSILON: f32 = 1e-3;
+
+/// angle of the bend given to straight joints by `IKChain::bend_to_pole`
+const POLE_NUDGE: f32 = 0.01;
+
+fn rotate_around(positions: &mut [Vec2], pivot: Vec2, angle: f32) {
+    let rotation = Vec2::from_angle(angle);
+    for pos in positions.iter_mut() {
+        *pos = pivot + rotation.rotate(*pos - pivot);
+    }
+}
+
+/// a chain of an IK tree, solved with `FabrikSolver::solve_tree`
+#[derive(Clone, Copy, Debug)]
+pub struct IKBranch<'a> {
+    pub chain: IKChain<'a>,
+
+    /// target of the effector of the branch
+    /// a branch without target just follows its sub-base
+    pub target: Option<Vec2>,
+
+    /// index of the parent branch, and index of the joint of the parent branch this branch starts from (its sub-base)
+    /// `None` for a root, which anchor won't move
+    pub parent: Option<(usize, usize)>,
+}
+
+/// absolute positions and angles of the joints of a chain
+/// indexed from the anchor (0) to the effector
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IKPose {
+    pub positions: Vec<Vec2>,
+    pub angles: Vec<f32>,
+}
+
+impl IKPose {
+    pub fn new(positions: Vec<Vec2>, angles: Vec<f32>) -> Self {
+        Self { positions, angles }
+    }
+
+    /// pose where each joint points towards the next one
+    /// and the effector continues the direction of the last bone
+    pub fn from_positions(positions: Vec<Vec2>) -> Self {
+        let mut angles = positions
+            .windows(2)
+            .map(|w| (w[1] - w[0]).to_angle())
+            .collect::<Vec<_>>();
+        if let Some(&last) = angles.last() {
+            angles.push(last);
+        }
+        Self { positions, angles }
+    }
+
+    /// pose from the position of the anchor and the absolute angle of each joint
+    /// each bone follows the angle of the joint it starts from
+    pub fn from_angles(anchor: Vec2, angles: Vec<f32>, lengths: &[f32]) -> Self {
+        let mut positions = Vec::with_capacity(angles.len());
+        positions.push(anchor);
+        for (&angle, &length) in angles.iter().zip(lengths) {
+            let prev = *positions.last().unwrap();
+            positions.push(prev + Vec2::from_angle(angle) * length);
+        }
+        Self { positions, angles }
+    }
+
+    /// wether all the positions and angles are finite
+    pub fn is_finite(&self) -> bool {
+        self.positions.iter().all(|p| p.is_finite()) && self.angles.iter().all(|a| a.is_finite())
+    }
+
+    /// blend from `from` to this pose, 0 being `from` and 1 this pose
+    /// the anchor position and the joint angles are interpolated
+    /// and the other joints placed along the interpolated bones, so that they keep their `lengths`
+    pub fn blend(&mut self, from: &IKPose, lengths: &[f32], weight: f32) {
+        let lerp_angle = |from: f32, to: f32| {
+            from + Vec2::from_angle(from).angle_to(Vec2::from_angle(to)) * weight
+        };
+
+        // position of the previous joint, before and after blending
+        let mut to_prev = self.positions[0];
+        let mut prev = from.positions[0].lerp(to_prev, weight);
+        self.positions[0] = prev;
+
+        for (i, &length) in lengths.iter().enumerate() {
+            let from_dir = (from.positions[i + 1] - from.positions[i]).to_angle();
+            let to_dir = (self.positions[i + 1] - to_prev).to_angle();
+            to_prev = self.positions[i + 1];
+            prev += Vec2::from_angle(lerp_angle(from_dir, to_dir)) * length;
+            self.positions[i + 1] = prev;
+        }
+
+        for (angle, &from) in self.angles.iter_mut().zip(&from.angles) {
+            *angle = lerp_angle(from, *angle);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// chain with a fixed anchor, and nothing but its bones and constraints
+    pub(crate) fn chain<'a>(
+        lengths: &'a [f32],
+        rest_angles: &'a [f32],
+        constraints: &'a [Option<JointConstraint>],
+    ) -> IKChain<'a> {
+        IKChain {
+            lengths,
+            rest_angles,
+            constraints,
+            iterations: 50,
+            epsilon: 0.01,
+            anchor: AnchorMode::Fixed,
+            anchor_rest: Vec2::ZERO,
+            pole: None,
+            target_angle: None,
+            orientation_weight: 1.,
+            softness: 0.,
+        }
+    }
+
+    /// straight pose along X, starting at the origin
+    pub(crate) fn straight(lengths: &[f32]) -> IKPose {
+        let mut positions = vec![Vec2::ZERO];
+        for &length in lengths {
+            positions.push(*positions.last().unwrap() + Vec2::X * length);
+        }
+        IKPose::from_positions(positions)
+    }
+
+    #[test]
+    fn floating_anchor_only_follows_out_of_reach() {
+        let lengths = [10., 10.];
+        let mut chain = chain(&lengths, &[0.; 3], &[None; 3]);
+        chain.anchor = AnchorMode::Floating {
+            stiffness: 0.,
+            max_displacement: None,
+        };
+        let solvers: [&dyn IKSolver; 4] = [
+            &crate::FabrikSolver,
+            &crate::CcdSolver,
+            &crate::JacobianSolver::new(1.),
+            &crate::TwoBoneSolver::default(),
+        ];
+
+        for solver in solvers {
+            let target = Vec2::new(15., 5.);
+            let pose = chain.solve(solver, straight(&lengths), target);
+            assert_eq!(pose.positions[0], Vec2::ZERO, "{solver:?}");
+            assert!(pose.positions[2].distance(target) < 0.1, "{solver:?}");
+
+            let target = Vec2::new(60., 0.);
+            let pose = chain.solve(solver, straight(&lengths), target);
+            assert!(
+                pose.positions[0].distance(Vec2::X * 40.) < 1e-3,
+                "{solver:?}"
+            );
+            assert!(pose.positions[2].distance(target) < 1e-3, "{solver:?}");
+        }
+
+        // the anchor only follows part of it with stiffness
+        chain.anchor = AnchorMode::Floating {
+            stiffness: 0.5,
+            max_displacement: Some(15.),
+        };
+        assert_eq!(
+            chain.anchor_pos(Vec2::ZERO, Vec2::new(40., 0.)),
+            Vec2::X * 10.
+        );
+        assert_eq!(
+            chain.anchor_pos(Vec2::ZERO, Vec2::new(60., 0.)),
+            Vec2::X * 15.
+        );
+    }
+
+    #[test]
+    fn softness_keeps_floating_anchor() {
+        let lengths = [10., 10.];
+        let mut chain = chain(&lengths, &[0.; 3], &[None; 3]);
+        chain.softness = 5.;
+        chain.anchor = AnchorMode::Floating {
+            stiffness: 0.5,
+            max_displacement: None,
+        };
+
+        // the anchor follows half of the 40 beyond the reach, the chain eases into the other half
+        let pose = chain.solve(&crate::FabrikSolver, straight(&lengths), Vec2::new(0., 60.));
+        assert!(pose.positions[0].distance(Vec2::Y * 20.) < 1e-3);
+        let dist = pose.positions[2].distance(pose.positions[0]);
+        assert!(dist > 19. && dist < 20., "{dist}");
+    }
+
+    /// distance between each consecutive joints
+    pub(crate) fn bone_lengths(pose: &IKPose) -> Vec<f32> {
+        pose.positions
+            .windows(2)
+            .map(|w| w[0].distance(w[1]))
+            .collect()
+    }
+
+    /// wether every bone of `pose` is within the joint constraint of the joint it starts from
+    pub(crate) fn within_constraints(chain: &IKChain, pose: &IKPose) -> bool {
+        let mut prev_dir = chain.anchor_dir();
+        pose.positions
+            .windows(2)
+            .zip(chain.constraints)
+            .all(|(w, c)| {
+                let dir = (w[1] - w[0]).normalize();
+                let angle = prev_dir.angle_to(dir);
+                prev_dir = dir;
+                c.is_none_or(|JointConstraint { ccw, cw }| {
+                    angle <= ccw + LIMIT_EPSILON && angle >= -cw - LIMIT_EPSILON
+                })
+            })
+    }
+
+    #[test]
+    fn soft_target_eases_below_max_reach() {
+        let lengths = [10., 10.];
+        let mut chain = chain(&lengths, &[0.; 3], &[None; 3]);
+        chain.softness = 5.;
+
+        // untouched until the last `softness` of the reach
+        assert_eq!(chain.soft_target(Vec2::ZERO, Vec2::X * 12.), Vec2::X * 12.);
+
+        let mut prev = 0.;
+        for i in 0..20 {
+            let target = Vec2::new(i as f32, 2. * i as f32);
+            let dist = chain.soft_target(Vec2::ZERO, target).length();
+            assert!(dist >= prev, "{i}");
+            assert!(dist < 20., "{i}");
+            prev = dist;
+        }
+
+        // far away, the max reach is only approached
+        let dist = chain.soft_target(Vec2::ZERO, Vec2::X * 1000.).length();
+        assert!(dist >= prev && dist <= 20.);
+    }
+
+    #[test]
+    fn blend_keeps_bone_lengths() {
+        let lengths = [10., 5., 8.];
+        let from = straight(&lengths);
+        let to = IKPose::from_angles(Vec2::new(3., 4.), vec![2., -1., 0.5, 0.5], &lengths);
+
+        for weight in [0., 0.25, 0.5, 0.75, 1.] {
+            let mut pose = to.clone();
+            pose.blend(&from, &lengths, weight);
+            for (length, expected) in bone_lengths(&pose).into_iter().zip(lengths) {
+                assert!((length - expected).abs() < 1e-4, "{weight}");
+            }
+        }
+
+        let mut pose = to.clone();
+        pose.blend(&from, &lengths, 0.);
+        assert!(pose.positions[3].distance(from.positions[3]) < 1e-4);
+    }
+}
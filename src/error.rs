@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+use std::fmt;
+
+/// why an IK chain couldn't be mapped or solved
+/// the chain is skipped, and an `IKFailed` event is triggered on its `IKConstraint` entity
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub enum IKError {
+    /// an entity of the chain, the parent of one, or the target, doesn't exist or has no transforms
+    MissingEntity(Entity),
+    /// the chain has less than 2 entities
+    ChainTooShort(usize),
+    /// an entity is more than once in the chain
+    DuplicateEntity(Entity),
+    /// an entity of the chain isn't below the previous one, while some entities of the chain are parented to others
+    Disconnected(Entity, Entity),
+    /// two consecutive entities of the chain are at the same position
+    ZeroLengthBone(Entity, Entity),
+    /// the `JointConstraint` of a joint has angles outside of -PI..PI, or an empty range
+    InvalidJointConstraint(Entity),
+    /// a branch of an `IKTree` is listed before the branch its anchor is a joint of
+    UnorderedBranch(Entity),
+    /// a joint has no rest data, the chain has to be mapped (see `map_new_ik`)
+    UnmappedJoint(Entity),
+    /// the solver produced a position or an angle that is NaN or infinite
+    NaNPose,
+}
+
+impl fmt::Display for IKError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IKError::MissingEntity(e) => write!(f, "unable to find entity {} used by IK chain", e),
+            IKError::ChainTooShort(len) => {
+                write!(f, "IK chain has {} entities, it needs at least 2", len)
+            }
+            IKError::DuplicateEntity(e) => write!(f, "entity {} is more than once in IK chain", e),
+            IKError::Disconnected(a, b) => write!(
+                f,
+                "entity {} of IK chain is not a descendant of the previous entity {}",
+                b, a
+            ),
+            IKError::ZeroLengthBone(a, b) => {
+                write!(
+                    f,
+                    "entities {} and {} of IK chain are at the same position",
+                    a, b
+                )
+            }
+            IKError::InvalidJointConstraint(e) => write!(
+                f,
+                "joint constraint of entity {} of IK chain must be within -PI..PI and not empty",
+                e
+            ),
+            IKError::UnorderedBranch(e) => write!(
+                f,
+                "IK tree branch {} is listed before the branch its anchor belongs to",
+                e
+            ),
+            IKError::UnmappedJoint(e) => write!(f, "joint {} of IK chain is not mapped", e),
+            IKError::NaNPose => write!(f, "IK chain was solved to a NaN pose"),
+        }
+    }
+}
+
+impl std::error::Error for IKError {}
+
+/// triggered on the entity holding an `IKConstraint` that couldn't be mapped or solved
+/// observe it with `app.add_observer(|failed: On<IKFailed>| ...)`
+#[derive(EntityEvent, Clone, Copy, Debug)]
+pub struct IKFailed {
+    /// entity holding the `IKConstraint`
+    pub entity: Entity,
+    pub error: IKError,
+}
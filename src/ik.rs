@@ -1,30 +1,127 @@
 use bevy::{
-    ecs::query::QueryEntityError, platform::collections::HashMap, prelude::*,
+    ecs::{
+        entity::{EntityHashMap, EntityHashSet},
+        schedule::{InternedScheduleLabel, ScheduleLabel},
+    },
+    platform::collections::HashMap,
+    prelude::*,
+    tasks::{ComputeTaskPool, TaskPool},
     transform::plugins::TransformSystems,
 };
 use std::f32::consts::{FRAC_PI_2, PI};
 
+use crate::{
+    reach::ReachFallback, record_ik, status::update_status, AnchorMode, BendDirection, CcdSolver,
+    FabrikSolver, IKBranch, IKChain, IKError, IKFailed, IKPose, IKReach, IKSolver, IKStatus,
+    JacobianSolver, OutOfReachMode, TwoBoneSolver,
+};
+
 /// add this plugin to your app to have IK constraints solved every frame
-pub struct IKPlugin;
+///
+/// by default, the IK systems run in `PostUpdate`, after the transforms are propagated
+/// in another schedule (ie: `FixedPostUpdate` to run with the physics), they see the global transforms of the last propagation
+pub struct IKPlugin {
+    /// schedule the IK systems run in
+    pub schedule: InternedScheduleLabel,
+
+    /// wether to draw the debug gizmos when `DebugIK` is inserted
+    /// it needs the gizmos plugin
+    pub debug: bool,
+
+    /// wether to compute the rest data of new `IKConstraint`s
+    /// without it, `map_new_ik` must be run on new chains before they are solved
+    pub auto_map: bool,
+}
+
+impl Default for IKPlugin {
+    fn default() -> Self {
+        Self {
+            schedule: PostUpdate.intern(),
+            debug: true,
+            auto_map: true,
+        }
+    }
+}
+
+impl IKPlugin {
+    /// run the IK systems in `schedule`
+    /// default is `PostUpdate`
+    pub fn in_schedule(mut self, schedule: impl ScheduleLabel) -> Self {
+        self.schedule = schedule.intern();
+        self
+    }
+
+    /// default is true
+    pub fn with_debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// default is true
+    pub fn with_auto_map(mut self, auto_map: bool) -> Self {
+        self.auto_map = auto_map;
+        self
+    }
+}
+
+fn warn_ik_failed(failed: On<IKFailed>) {
+    warn!("{} ({})", failed.error, failed.entity);
+}
+
+/// system sets of the IK systems, run one after the other
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IKSystems {
+    /// compute the rest data of new `IKConstraint`s
+    Map,
+    /// solve the chains, propagate them to their descendants, and record the ones with an `IKRecording`
+    Solve,
+    /// draw the debug gizmos
+    Debug,
+}
 
 impl Plugin for IKPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            PostUpdate,
-            (map_new_ik, solve_ik, debug_ik)
+        app.configure_sets(
+            self.schedule,
+            (IKSystems::Map, IKSystems::Solve, IKSystems::Debug)
                 .chain()
                 .after(TransformSystems::Propagate),
         )
+        .add_systems(
+            self.schedule,
+            (solve_ik, propagate_ik, record_ik)
+                .chain()
+                .in_set(IKSystems::Solve),
+        )
         .register_type::<DebugIK>()
         .register_type::<Bone>()
         .register_type::<JointRest>()
         .register_type::<JointConstraint>()
-        .register_type::<IKConstraint>();
+        .register_type::<IKConstraint>()
+        .register_type::<IKTree>()
+        .register_type::<AnchorMode>()
+        .register_type::<FabrikSolver>()
+        .register_type::<CcdSolver>()
+        .register_type::<JacobianSolver>()
+        .register_type::<TwoBoneSolver>()
+        .register_type::<BendDirection>()
+        .register_type::<IKError>()
+        .register_type::<IKStatus>()
+        .init_resource::<IKSolvedJoints>()
+        .add_observer(warn_ik_failed);
+
+        if self.auto_map {
+            app.add_systems(self.schedule, map_new_ik.in_set(IKSystems::Map));
+        }
+
+        if self.debug {
+            app.add_systems(self.schedule, debug_ik.in_set(IKSystems::Debug));
+        }
     }
 }
 
 /// insert this resource to enable debug gizmos
-#[derive(Resource, Reflect)]
+#[derive(Resource, Reflect, Default)]
 pub struct DebugIK {
     /// size of the circle to draw on joints
     pub joints: Option<f32>,
@@ -32,16 +129,8 @@ pub struct DebugIK {
     pub bones: bool,
     /// draw ik joint constraints
     pub constraints: Option<f32>,
-}
-
-impl Default for DebugIK {
-    fn default() -> Self {
-        Self {
-            joints: None,
-            bones: false,
-            constraints: None,
-        }
-    }
+    /// draw the region each effector can reach
+    pub reach: bool,
 }
 
 impl DebugIK {
@@ -51,6 +140,7 @@ impl DebugIK {
             joints: Some(scale / 2.),
             bones: false,
             constraints: Some(scale),
+            reach: false,
         }
     }
 
@@ -59,6 +149,7 @@ impl DebugIK {
             joints: None,
             bones: false,
             constraints: None,
+            reach: false,
         }
     }
 }
@@ -66,7 +157,7 @@ impl DebugIK {
 /// length constraint of a bone (which is a relation between two `Joint`s)
 #[derive(Clone, Debug, Reflect)]
 pub struct Bone {
-    length: f32,
+    pub(crate) length: f32,
 }
 
 impl Default for Bone {
@@ -84,7 +175,7 @@ impl Bone {
 /// absolute angle of the bone in the resting position
 #[derive(Clone, Default, Debug, Reflect)]
 pub struct JointRest {
-    angle: f32,
+    pub(crate) angle: f32,
 }
 
 impl JointRest {
@@ -94,14 +185,14 @@ impl JointRest {
 }
 
 /// angle constraint of a joint
-#[derive(Clone, Debug, Reflect)]
+#[derive(Clone, Copy, Debug, Reflect)]
 pub struct JointConstraint {
     /// max counter clockwise angle from initial angle
     /// must be between -PI and PI
-    ccw: f32,
+    pub(crate) ccw: f32,
     /// max clockwise angle from initial angle
     /// must be between -PI and PI
-    cw: f32,
+    pub(crate) cw: f32,
 }
 
 impl Default for JointConstraint {
@@ -120,13 +211,67 @@ impl JointConstraint {
 pub enum IKTarget {
     None,
     Pos(Vec2),
+    /// absolute position, and absolute rotation (around z, in radians) of the effector
+    PosRot(Vec2, f32),
     Entity(Entity),
+    /// a point in the local space of an entity (ie: the handle of a sword)
+    /// with `inherit_rot`, the effector also takes the rotation of the entity
+    EntityOffset {
+        entity: Entity,
+        offset: Vec2,
+        inherit_rot: bool,
+    },
+}
+
+impl IKTarget {
+    /// absolute position of the target
+    /// and absolute rotation of the effector, if the target has one
+    fn pose(
+        &self,
+        transforms: &Query<(&mut GlobalTransform, &mut Transform)>,
+    ) -> Result<Option<(Vec2, Option<f32>)>, IKError> {
+        Ok(match *self {
+            IKTarget::None => None,
+            IKTarget::Pos(target) => Some((target, None)),
+            IKTarget::PosRot(target, rot) => Some((target, Some(rot))),
+            IKTarget::Entity(target) => {
+                Some((global(transforms, target)?.translation().xy(), None))
+            }
+            IKTarget::EntityOffset {
+                entity,
+                offset,
+                inherit_rot,
+            } => {
+                let gtr = global(transforms, entity)?;
+                let pos = gtr.transform_point(offset.extend(0.)).xy();
+                let rot = inherit_rot.then(|| gtr.rotation().to_euler(EulerRot::ZXY).0);
+                Some((pos, rot))
+            }
+        })
+    }
+
+    /// absolute position of the target
+    fn pos(
+        &self,
+        transforms: &Query<(&mut GlobalTransform, &mut Transform)>,
+    ) -> Result<Option<Vec2>, IKError> {
+        Ok(self.pose(transforms)?.map(|(pos, _)| pos))
+    }
+
+    fn entity(&self) -> Option<Entity> {
+        match *self {
+            IKTarget::Entity(entity) | IKTarget::EntityOffset { entity, .. } => Some(entity),
+            _ => None,
+        }
+    }
 }
 
 /// add this component to an entity to make it the effector of an IK chain
 /// all the entities in the chain must have a `Transform` and `GlobalTransform` component
 /// their transforms and global transforms will be updated to satisfy the IK constraints without breaking the parent-child hierarchy
+/// the result of each solve is written to its `IKStatus`
 #[derive(Component, Debug, Reflect)]
+#[require(IKStatus)]
 pub struct IKConstraint {
     /// target of the IK constraint
     pub target: IKTarget,
@@ -136,7 +281,10 @@ pub struct IKConstraint {
     ///   it won't move, but it can rotate (ie: the shoulder)
     /// the last entity in the chain is the effector
     ///   it will move and rotate to the target (ie: the hand)
-    /// so a chain needs at least 3 entities (anchor, joint, effector)
+    /// so a chain needs at least 2 entities (anchor, effector), most have joints in between
+    /// entities must be unique, each one must be a descendant of the previous one
+    /// (or none of them is a descendant of another, ie: unparented or siblings)
+    /// and two consecutive entities can't be at the same position
     ///
     /// chain example: [shoulder, elbow, wrist, hand]
     /// the body wont be affected by the IK
@@ -170,6 +318,56 @@ pub struct IKConstraint {
     /// epsilon to consider the constraint solved
     /// must be smaller than the smaller distance constraint
     pub epsilon: f32,
+
+    /// algorithm used to solve the chain
+    /// default is `FabrikSolver`
+    #[reflect(ignore)]
+    pub solver: Box<dyn IKSolver>,
+
+    /// wether the anchor can be pulled by the effector
+    /// default is `AnchorMode::Fixed`
+    pub anchor: AnchorMode,
+
+    /// local translation of the anchor at rest
+    /// a floating anchor is pulled back towards it
+    /// it will get computed automatically when the chain is created
+    pub anchor_rest_pos: Vec3,
+
+    /// position the middle joints bend towards (ie: in front of the knee, behind the elbow)
+    /// so that the chain keeps bending the same way, even after going straight
+    /// default is `IKTarget::None`, the chain bends the way it already is
+    pub pole: IKTarget,
+
+    /// how much the last bone rotates to let the effector reach the rotation of the target, if it has one
+    /// between 0 (only the effector rotates) and 1 (the rotation is reached whenever the joint constraints allow it)
+    /// default is 1
+    pub orientation_weight: f32,
+
+    /// distance from the max reach under which the effector starts lagging behind the target
+    /// so that knees and elbows don't snap straight
+    /// a floating anchor still follows the target itself, only the reach around its new position is softened
+    /// not used by `IKTree`s
+    /// default is 0 (disabled)
+    pub softness: f32,
+
+    /// blend between the pose the chain had before being solved (ie: the one from an animation) and the solved one
+    /// between 0 (not solved at all) and 1 (fully solved)
+    /// default is 1
+    pub weight: f32,
+
+    /// what the chain does when its target is out of reach
+    /// not used by `IKTree`s
+    /// default is `OutOfReachMode::Stretch`
+    pub out_of_reach: OutOfReachMode,
+
+    /// chain the rest data was last computed for
+    /// `None` until it's mapped for the first time, or after `remap`
+    #[reflect(ignore)]
+    mapped_chain: Option<Vec<Entity>>,
+
+    /// wether the rest data was computed successfully
+    #[reflect(ignore)]
+    mapped: bool,
 }
 
 impl IKConstraint {
@@ -184,6 +382,16 @@ impl IKConstraint {
             joint_constraints: HashMap::new(),
             rest_data: HashMap::new(),
             anchor_parent_rest_rot: 0.0,
+            solver: Box::new(FabrikSolver),
+            anchor: AnchorMode::Fixed,
+            anchor_rest_pos: Vec3::ZERO,
+            pole: IKTarget::None,
+            orientation_weight: 1.,
+            softness: 0.,
+            weight: 1.,
+            out_of_reach: OutOfReachMode::Stretch,
+            mapped_chain: None,
+            mapped: false,
         }
     }
 
@@ -205,6 +413,53 @@ impl IKConstraint {
         self
     }
 
+    /// set the algorithm used to solve the chain
+    /// default is `FabrikSolver`
+    pub fn with_solver(mut self, solver: impl IKSolver) -> Self {
+        self.solver = Box::new(solver);
+        self
+    }
+
+    /// let the anchor be pulled by the effector when the target is out of reach
+    /// default is `AnchorMode::Fixed`
+    pub fn with_anchor(mut self, anchor: AnchorMode) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// make the middle joints bend towards `pole`
+    pub fn with_pole(mut self, pole: IKTarget) -> Self {
+        self.pole = pole;
+        self
+    }
+
+    /// how much the chain bends for the effector to reach the rotation of the target
+    /// default is 1
+    pub fn with_orientation_weight(mut self, weight: f32) -> Self {
+        self.orientation_weight = weight;
+        self
+    }
+
+    /// ease the effector into full extension over the last `softness` of the chain's reach
+    /// default is 0 (disabled)
+    pub fn with_softness(mut self, softness: f32) -> Self {
+        self.softness = softness;
+        self
+    }
+
+    /// blend the solved pose with the one the chain had before being solved
+    /// default is 1
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// default is `OutOfReachMode::Stretch`
+    pub fn with_out_of_reach(mut self, mode: OutOfReachMode) -> Self {
+        self.out_of_reach = mode;
+        self
+    }
+
     pub fn with_target(mut self, target: IKTarget) -> Self {
         self.target = target;
         self
@@ -218,327 +473,988 @@ impl IKConstraint {
         self.target = IKTarget::None;
     }
 
-    /// set absolute posiiton of an entity
-    /// wether it's an orphan entity or a child of another entity
-    fn set_position(
+    /// rotation of the anchor's parent since the chain was mapped
+    /// rest angles follow it, so that the chain moves with its parent
+    fn parent_rot_offset(
         &self,
-        entity: Entity,
-        pos: Vec2,
         parents: &Query<&ChildOf>,
-        transforms: &mut Query<(&mut GlobalTransform, &mut Transform)>,
-    ) {
-        match parents.get(entity) {
+        transforms: &Query<(&mut GlobalTransform, &mut Transform)>,
+    ) -> Result<f32, IKError> {
+        match parents.get(self.chain[0]) {
             Ok(parent) => {
-                if let Ok([(mut gtr, mut tr), (parent_gtr, _)]) =
-                    transforms.get_many_mut([entity, parent.parent()])
-                {
-                    let new_global_tr = GlobalTransform::from(Transform {
-                        translation: pos.extend(gtr.translation().z),
-                        rotation: gtr.rotation(),
-                        scale: gtr.scale(),
-                    });
-                    *tr = new_global_tr.reparented_to(&parent_gtr);
-                    *gtr = new_global_tr;
-                }
-            }
-            Err(_) => {
-                if let Ok((mut gtr, mut tr)) = transforms.get_mut(entity) {
-                    tr.translation = pos.extend(tr.translation.z);
-                    *gtr = GlobalTransform::from(*tr);
-                }
+                let parent_z_rot = global(transforms, parent.parent())?
+                    .rotation()
+                    .to_euler(EulerRot::ZXY)
+                    .0;
+
+                Ok(parent_z_rot - self.anchor_parent_rest_rot)
             }
+            Err(_) => Ok(0.0),
         }
     }
 
-    /// set absolute rotation of an entity
-    /// wether it's an orphan entity or a child of another entity
-    fn set_rotation(
+    /// z rotation and absolute angle of a joint at rest
+    fn rest(&self, e: Entity) -> Result<(f32, f32), IKError> {
+        match (self.rest_data.get(&e), self.joint_data.get(&e)) {
+            (Some(rot), Some(joint)) => Ok((rot.to_euler(EulerRot::ZXY).0, joint.angle)),
+            _ => Err(IKError::UnmappedJoint(e)),
+        }
+    }
+
+    /// recompute the rest data of the chain (bone lengths, angles and rotations) from its current pose
+    /// it's done automatically when `chain` is edited or when a joint is reparented
+    pub fn remap(&mut self) {
+        self.mapped_chain = None;
+    }
+
+    /// wether the rest data is up to date with `chain`
+    pub fn is_mapped(&self) -> bool {
+        self.mapped && self.mapped_chain.as_ref() == Some(&self.chain)
+    }
+
+    /// bone lengths and joint constraints of a mapped chain, indexed like in `IKChain`
+    fn reach_data(&self) -> Option<(Vec<f32>, Vec<Option<JointConstraint>>)> {
+        if !self.is_mapped() {
+            return None;
+        }
+
+        let lengths = self
+            .chain
+            .windows(2)
+            .map(|w| self.bone_data.get(&(w[0], w[1])).map(|bone| bone.length))
+            .collect::<Option<Vec<_>>>()?;
+        let constraints = self
+            .chain
+            .iter()
+            .map(|e| self.joint_constraints.get(e).copied())
+            .collect();
+
+        Some((lengths, constraints))
+    }
+
+    /// max distance between the anchor and the effector
+    /// `None` if the chain isn't mapped
+    pub fn max_reach(&self) -> Option<f32> {
+        let (lengths, constraints) = self.reach_data()?;
+        Some(IKReach::new(Vec2::ZERO, Vec2::X, &lengths, &constraints).max)
+    }
+
+    /// min distance between the anchor and the effector, approximated with joint constraints (see `IKReach`)
+    /// `None` if the chain isn't mapped
+    pub fn min_reach(&self) -> Option<f32> {
+        let (lengths, constraints) = self.reach_data()?;
+        Some(IKReach::new(Vec2::ZERO, Vec2::X, &lengths, &constraints).min)
+    }
+
+    /// region the effector can reach from the current transform of the anchor
+    /// `None` if the chain isn't mapped, or if the anchor or its parent has no `GlobalTransform`
+    pub fn reach(
         &self,
-        entity: Entity,
-        rot: f32,
+        transforms: &Query<&GlobalTransform>,
         parents: &Query<&ChildOf>,
-        transforms: &mut Query<(&mut GlobalTransform, &mut Transform)>,
-    ) {
-        let base_rot = self.rest_data.get(&entity).unwrap();
-        let base_angle = self.joint_data.get(&entity).unwrap().angle;
-        let diff_from_rest = rot - base_angle;
+    ) -> Option<IKReach> {
+        let (lengths, constraints) = self.reach_data()?;
+        let (anchor, anchor_dir) = self.anchor_frame(transforms, parents)?;
+        Some(IKReach::new(anchor, anchor_dir, &lengths, &constraints))
+    }
 
-        match parents.get(entity) {
-            Ok(parent) => {
-                if let Ok([(mut gtr, mut tr), (parent_gtr, _)]) =
-                    transforms.get_many_mut([entity, parent.parent()])
-                {
-                    let new_global_tr = GlobalTransform::from(Transform {
-                        translation: gtr.translation(),
-                        rotation: Quat::from_rotation_z(diff_from_rest) * *base_rot,
-                        scale: gtr.scale(),
-                    });
+    /// wether the effector can reach `point` from the current transform of the anchor
+    /// false if the chain isn't mapped
+    pub fn can_reach(
+        &self,
+        point: Vec2,
+        transforms: &Query<&GlobalTransform>,
+        parents: &Query<&ChildOf>,
+    ) -> bool {
+        self.reach(transforms, parents)
+            .is_some_and(|reach| reach.contains(point))
+    }
 
-                    *tr = new_global_tr.reparented_to(&parent_gtr);
+    /// absolute position of the anchor, and reference direction of its constraint
+    /// which follows the rotation of the anchor's parent
+    fn anchor_frame(
+        &self,
+        transforms: &Query<&GlobalTransform>,
+        parents: &Query<&ChildOf>,
+    ) -> Option<(Vec2, Vec2)> {
+        let anchor = self.chain[0];
+        let JointRest { angle } = self.joint_data.get(&anchor)?;
+        let pos = transforms.get(anchor).ok()?.translation().xy();
 
-                    *gtr = new_global_tr;
-                }
-            }
-            Err(_) => {
-                if let Ok((mut gtr, mut tr)) = transforms.get_mut(entity) {
-                    tr.rotation = *base_rot * Quat::from_rotation_z(diff_from_rest);
-                    *gtr = GlobalTransform::from(*tr);
-                }
+        let dir = match parents.get(anchor) {
+            Ok(parent) => {
+                let parent_gtr = transforms.get(parent.parent()).ok()?;
+                let parent_z_rot = parent_gtr.rotation().to_euler(EulerRot::ZXY).0;
+
+                Vec2::from_angle(angle + parent_z_rot - self.anchor_parent_rest_rot)
             }
-        }
+            Err(_) => Vec2::from_angle(*angle),
+        };
+
+        Some((pos, dir))
     }
 
-    fn get_effector_dir(
+    /// absolute position of the target
+    fn target_pos(
         &self,
-        target: Vec2,
-        effector_gtr: GlobalTransform,
-        transforms: &mut Query<(&mut GlobalTransform, &mut Transform)>,
-    ) -> Vec2 {
-        if !(target - effector_gtr.translation().xy())
-            .normalize()
-            .is_nan()
-        {
-            (target - effector_gtr.translation().xy()).normalize()
-        } else {
-            // if effector is already at target pos
-            // use the angle from the prev bone
-            let prev = self.chain[self.chain.len() - 2];
-            let prev_gtr = transforms.get(prev).unwrap().0;
-            (effector_gtr.translation().xy() - prev_gtr.translation().xy()).normalize()
+        transforms: &Query<(&mut GlobalTransform, &mut Transform)>,
+    ) -> Result<Option<Vec2>, IKError> {
+        self.target.pos(transforms)
+    }
+
+    /// entities outside of the chain whose transforms are read while solving it
+    /// the parents of the joints, the target entity and the pole entity
+    fn read_entities<'a, 'w, 's, 'd>(
+        &'a self,
+        parents: &'a Query<'w, 's, &'d ChildOf>,
+    ) -> impl Iterator<Item = Entity> + use<'a, 'w, 's, 'd> {
+        self.chain
+            .iter()
+            .filter_map(|&e| parents.get(e).ok().map(ChildOf::parent))
+            .chain(self.target.entity())
+            .chain(self.pole.entity())
+    }
+
+    /// gather the plain data of the chain into `buffers`
+    /// with `follow_parent`, rest angles follow the rotation of the anchor's parent
+    fn gather(
+        &self,
+        buffers: &mut ChainBuffers,
+        follow_parent: bool,
+        parents: &Query<&ChildOf>,
+        transforms: &Query<(&mut GlobalTransform, &mut Transform)>,
+    ) -> Result<(), IKError> {
+        let offset = match follow_parent {
+            true => self.parent_rot_offset(parents, transforms)?,
+            false => 0.0,
+        };
+
+        buffers.lengths.clear();
+        for w in self.chain.windows(2) {
+            let bone = self
+                .bone_data
+                .get(&(w[0], w[1]))
+                .ok_or(IKError::UnmappedJoint(w[1]))?;
+            buffers.lengths.push(bone.length);
         }
+
+        // rest data and current absolute pose of the chain
+        buffers.rest_angles.clear();
+        buffers.constraints.clear();
+        buffers.pose.positions.clear();
+        buffers.pose.angles.clear();
+        for &e in self.chain.iter() {
+            let (rest_rot, rest_angle) = self.rest(e)?;
+            let gtr = global(transforms, e)?;
+            let diff_from_rest = gtr.rotation().to_euler(EulerRot::ZXY).0 - rest_rot;
+
+            buffers.rest_angles.push(rest_angle + offset);
+            buffers
+                .constraints
+                .push(self.joint_constraints.get(&e).copied());
+            buffers.pose.positions.push(gtr.translation().xy());
+            buffers.pose.angles.push(rest_angle + diff_from_rest);
+        }
+
+        buffers.anchor_rest = match parents.get(self.chain[0]) {
+            Ok(parent) => global(transforms, parent.parent())?
+                .transform_point(self.anchor_rest_pos)
+                .xy(),
+            Err(_) => self.anchor_rest_pos.xy(),
+        };
+        buffers.pole = self.pole.pos(transforms)?;
+
+        // from a rotation of the effector to the angle of its joint
+        let (rest_rot, rest_angle) = self.rest(self.chain[self.chain.len() - 1])?;
+        buffers.target_angle = self
+            .target
+            .pose(transforms)?
+            .and_then(|(_, rot)| rot)
+            .map(|rot| rot - rest_rot + rest_angle);
+
+        Ok(())
     }
 
-    fn solve_iteration(
+    /// check that the chain can be solved
+    /// `chain_transforms` are the transforms of the chain entities
+    fn validate(
         &self,
-        target: Vec2,
+        chain_transforms: &[(&Transform, &GlobalTransform)],
         parents: &Query<&ChildOf>,
-        transforms: &mut Query<(&mut GlobalTransform, &mut Transform)>,
-    ) {
-        let effector = self.chain.last().unwrap();
-        let anchor = self.chain.first().unwrap();
+    ) -> Result<(), IKError> {
+        let mut entities = EntityHashSet::default();
+        for &e in self.chain.iter() {
+            if !entities.insert(e) {
+                return Err(IKError::DuplicateEntity(e));
+            }
+        }
+
+        // chains of unparented (or sibling) entities are allowed
+        // but as soon as one entity is below another, each entity has to be below the previous one
+        let in_hierarchy = self
+            .chain
+            .iter()
+            .any(|&e| parents.iter_ancestors(e).any(|a| entities.contains(&a)));
+
+        for (w, t) in self.chain.windows(2).zip(chain_transforms.windows(2)) {
+            let (parent, child) = (w[0], w[1]);
 
-        let anchor_gtr = transforms.get(*anchor).unwrap().0.clone();
+            if in_hierarchy && !parents.iter_ancestors(child).any(|a| a == parent) {
+                return Err(IKError::Disconnected(parent, child));
+            }
+
+            let length = (t[1].1.translation() - t[0].1.translation()).xy().length();
+            if length <= f32::EPSILON {
+                return Err(IKError::ZeroLengthBone(parent, child));
+            }
+        }
+
+        for e in self.chain.iter() {
+            if let Some(&JointConstraint { ccw, cw }) = self.joint_constraints.get(e) {
+                let range = -PI..=PI;
+                if !range.contains(&ccw) || !range.contains(&cw) || -cw > ccw {
+                    return Err(IKError::InvalidJointConstraint(*e));
+                }
+            }
+        }
+
+        Ok(())
+    }
 
-        // absolute dir of the anchor
-        let anchor_dir = match parents.get(*anchor) {
+    /// compute the rest data of the chain from its current pose
+    fn map(
+        &mut self,
+        transforms: &Query<(&Transform, &GlobalTransform)>,
+        parents: &Query<&ChildOf>,
+    ) -> Result<(), IKError> {
+        if self.chain.len() < 2 {
+            return Err(IKError::ChainTooShort(self.chain.len()));
+        }
+
+        // cache all the transforms
+        // it might be useless perf wise, but it avoid a lot of unwraps
+        let chain_transforms = self
+            .chain
+            .iter()
+            .map(|&e| transforms.get(e).map_err(|_| IKError::MissingEntity(e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.validate(&chain_transforms, parents)?;
+
+        self.anchor_rest_pos = chain_transforms[0].0.translation;
+
+        self.anchor_parent_rest_rot = match parents.get(self.chain[0]) {
             Ok(parent) => {
-                let parent_z_rot = transforms
+                transforms
                     .get(parent.parent())
-                    .unwrap()
-                    .0
+                    .map_err(|_| IKError::MissingEntity(parent.parent()))?
+                    .1
                     .rotation()
                     .to_euler(EulerRot::ZXY)
-                    .0;
-
-                Vec2::from_angle(
-                    self.joint_data.get(anchor).unwrap().angle + parent_z_rot
-                        - self.anchor_parent_rest_rot,
-                )
+                    .0
             }
-            Err(_) => Vec2::from_angle(self.joint_data.get(anchor).unwrap().angle),
+            Err(_) => 0.0,
         };
 
-        // bring the effector to the target position
-        self.set_position(*effector, target, parents, transforms);
+        for i in 0..self.chain.len() {
+            let e = self.chain[i];
+            let (_, gtr) = chain_transforms[i];
 
-        // pull the chain to the effector
-        // while respecting the length constraints
-        // iter from effector to anchor
-        // e1 will pull e0
-        for i in (1..self.chain.len()).rev() {
-            let e1 = self.chain[i];
-            let e0 = self.chain[i - 1];
+            self.rest_data.insert(e, gtr.rotation());
 
-            let bone = self.bone_data.get(&(e1, e0)).unwrap();
+            if let Some(prev_i) = i.checked_sub(1) {
+                let prev_e = self.chain[prev_i];
+                let (_, prev_gtr) = chain_transforms[prev_i];
 
-            let [(e1_gtr, _), (e0_gtr, _)] = transforms.get_many([e1, e0]).unwrap();
-            let e1_pos = e1_gtr.translation().xy();
-            let e0_pos = e0_gtr.translation().xy();
-            let new_e0_pos = e1_pos + (e0_pos - e1_pos).normalize() * bone.length;
-            self.set_position(e0, new_e0_pos, parents, transforms);
-        }
+                let dist = gtr.translation().xy().distance(prev_gtr.translation().xy());
+                self.bone_data.insert((e, prev_e), Bone::new(dist));
+                self.bone_data.insert((prev_e, e), Bone::new(dist));
+            }
 
-        let dir = self.get_effector_dir(
-            target,
-            transforms.get(*effector).unwrap().0.clone(),
-            transforms,
-        );
+            match i {
+                // we are at the anchor
+                // take the direction to the next joint as the angle
+                0 => {
+                    let anchor_gtr = chain_transforms[0].1;
+                    let anchor_child_gtr = chain_transforms[1].1;
+                    let dir = anchor_child_gtr.translation().xy() - anchor_gtr.translation().xy();
+                    self.joint_data.insert(e, JointRest::new(dir.to_angle()));
+                }
+                _ => {
+                    let (_, prev_gtr) = chain_transforms[i - 1];
 
-        self.set_rotation(*effector, dir.to_angle(), parents, transforms);
+                    let dir = gtr.translation().xy() - prev_gtr.translation().xy();
 
-        // bring the anchor back to its original position
-        self.set_position(*anchor, anchor_gtr.translation().xy(), parents, transforms);
+                    self.joint_data.insert(e, JointRest::new(dir.to_angle()));
+                }
+            }
+        }
 
-        // use the anchor's (potentially relative, if it has a parent) rotation as the original direction
-        // to also apply the angle constraint on the anchor rotation
-        let mut prev_dir = anchor_dir;
+        Ok(())
+    }
 
-        // pull the chain to the anchor
-        // while respecting the length and angle constraints
-        // iter from anchor to effector
-        // e0 will pull e1
-        // and rotate e0 accordingly
-        for i in 0..self.chain.len() - 1 {
-            let e0 = self.chain[i];
-            let e1 = self.chain[i + 1];
+    /// write a solved pose back into the transforms of the chain, starting at joint `from`
+    /// each entity is written once, from the anchor to the effector
+    /// so that children are placed relative to their already updated parents
+    /// the written entities are added to `solved` (see `IKSolvedJoints`)
+    fn apply_pose(
+        &self,
+        pose: &IKPose,
+        from: usize,
+        parents: &Query<&ChildOf>,
+        transforms: &mut Query<(&mut GlobalTransform, &mut Transform)>,
+        solved: &mut EntityHashMap<bool>,
+    ) {
+        // last written entity, most of the time the parent of the next one
+        let mut prev: Option<(Entity, GlobalTransform)> = None;
 
-            let [(e1_gtr, _), (e0_gtr, _)] = transforms.get_many([e1, e0]).unwrap();
-            let e1_pos = e1_gtr.translation().xy();
-            let e0_pos = e0_gtr.translation().xy();
+        for (i, &e) in self.chain.iter().enumerate().skip(from) {
+            let (Some(&base_rot), Some(&JointRest { angle: base_angle })) =
+                (self.rest_data.get(&e), self.joint_data.get(&e))
+            else {
+                continue;
+            };
+            let diff_from_rest = pose.angles[i] - base_angle;
+            let pos = pose.positions[i];
+
+            let parent_gtr = match parents.get(e) {
+                Ok(parent) => match prev {
+                    Some((prev, prev_gtr)) if prev == parent.parent() => Some(prev_gtr),
+                    _ => transforms.get(parent.parent()).ok().map(|(gtr, _)| *gtr),
+                },
+                Err(_) => None,
+            };
 
-            let mut dir = (e1_pos - e0_pos).normalize();
-            let mut dist = e1_pos.distance(e0_pos);
+            let Ok((mut gtr, mut tr)) = transforms.get_mut(e) else {
+                continue;
+            };
 
-            if let Some(bone) = self.bone_data.get(&(e0, e1)) {
-                dist = bone.length;
+            match parent_gtr {
+                Some(parent_gtr) => {
+                    let (scale, _, translation) = gtr.to_scale_rotation_translation();
+                    let new_global_tr = GlobalTransform::from(Transform {
+                        translation: pos.extend(translation.z),
+                        rotation: Quat::from_rotation_z(diff_from_rest) * base_rot,
+                        scale,
+                    });
+                    *tr = new_global_tr.reparented_to(&parent_gtr);
+                    *gtr = new_global_tr;
+                }
+                None => {
+                    tr.translation = pos.extend(tr.translation.z);
+                    tr.rotation = base_rot * Quat::from_rotation_z(diff_from_rest);
+                    *gtr = GlobalTransform::from(*tr);
+                }
             }
 
-            let angle = prev_dir.angle_to(dir);
-            let rotation = Mat2::from_angle(match self.joint_constraints.get(&e0) {
-                Some(&JointConstraint { ccw, cw }) => angle.clamp(-cw, ccw),
-                None => angle,
-            });
+            prev = Some((e, *gtr));
+            *solved.entry(e).or_default() |= i > 0;
+        }
+    }
+}
 
-            dir = (rotation * prev_dir).normalize();
+/// add this component to solve several `IKConstraint`s together, as the branches of a tree
+/// (ie: a spine with two arms, or a body with four legs)
+/// solved with the multiple end effectors extension of FABRIK
+///
+/// every chain must start either at a root (its anchor won't move)
+/// or at a joint of a previous chain, its sub-base, which will be placed on the average of what its branches want
+/// so for a body with four legs: [spine, front left leg, front right leg, back left leg, back right leg]
+/// with the legs anchored on the spine joints
+///
+/// the `target`, `weight` and joint constraints of each `IKConstraint` are still used, but their solver, iterations, epsilon, `out_of_reach` and `softness` are not
+#[derive(Component, Debug, Reflect)]
+pub struct IKTree {
+    /// effectors of the branches, holding their `IKConstraint`
+    /// parents must come before their children, or the tree fails with `IKError::UnorderedBranch`
+    pub branches: Vec<Entity>,
 
-            let new_e1_pos = e0_pos + dir * dist;
-            self.set_position(e1, new_e1_pos, parents, transforms);
+    /// max number of iterations to solve the tree
+    pub iterations: usize,
 
-            self.set_rotation(e0, dir.to_angle(), parents, transforms);
+    /// epsilon to consider the tree solved
+    /// when all the effectors with a target are within it
+    pub epsilon: f32,
+}
 
-            prev_dir = dir;
+impl IKTree {
+    pub fn new(branches: Vec<Entity>) -> Self {
+        Self {
+            branches,
+            iterations: 10,
+            epsilon: 1.0,
         }
+    }
+
+    /// set the number of iterations to solve the tree
+    /// default is 10
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    pub fn with_epsilon(mut self, epsilon: f32) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+}
 
-        // restrain the effector's angle
-        // since it doesnt happen in the loop above
-        let dir = self.get_effector_dir(
+/// plain data of a chain being solved
+#[derive(Default)]
+struct ChainBuffers {
+    lengths: Vec<f32>,
+    rest_angles: Vec<f32>,
+    constraints: Vec<Option<JointConstraint>>,
+    anchor_rest: Vec2,
+    pole: Option<Vec2>,
+    target_angle: Option<f32>,
+    pose: IKPose,
+    /// pose before solving, to blend with
+    start: IKPose,
+    /// why the chain couldn't be gathered or solved
+    error: Option<IKError>,
+    status: IKStatus,
+    fallback: ReachFallback,
+}
+
+impl ChainBuffers {
+    fn chain(&self, constraint: &IKConstraint) -> IKChain<'_> {
+        IKChain {
+            lengths: &self.lengths,
+            rest_angles: &self.rest_angles,
+            constraints: &self.constraints,
+            iterations: constraint.iterations,
+            epsilon: constraint.epsilon,
+            anchor: constraint.anchor,
+            anchor_rest: self.anchor_rest,
+            pole: self.pole,
+            target_angle: self.target_angle,
+            orientation_weight: constraint.orientation_weight,
+            softness: constraint.softness,
+        }
+    }
+
+    /// `dt` is the time since the last solve, for the transition of the `OutOfReachMode`
+    fn solve(&mut self, constraint: &IKConstraint, target: Vec2, dt: f32) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let weight = constraint.weight.clamp(0., 1.);
+        if weight < 1. {
+            self.start.clone_from(&self.pose);
+        }
+
+        let mut pose = std::mem::take(&mut self.pose);
+        let mut fallback = std::mem::take(&mut self.fallback);
+        let chain = self.chain(constraint);
+        let iterations = chain.solve_with_fallback(
+            constraint.solver.as_ref(),
+            &mut pose,
             target,
-            transforms.get(*effector).unwrap().0.clone(),
-            transforms,
+            constraint.out_of_reach,
+            &mut fallback,
+            dt,
         );
-        let angle = prev_dir.angle_to(dir);
-        let rotation = Mat2::from_angle(match self.joint_constraints.get(effector) {
-            Some(&JointConstraint { ccw, cw }) => angle.clamp(-cw, ccw),
-            None => angle,
-        });
-        let dir = rotation * prev_dir;
-        self.set_rotation(*effector, dir.to_angle(), parents, transforms);
+
+        if weight < 1. {
+            pose.blend(&self.start, &self.lengths, weight);
+        }
+        let status = pose
+            .is_finite()
+            .then(|| IKStatus::new(&chain, &pose, chain.anchor_dir(), target, iterations));
+        match status {
+            Some(status) => self.status = status,
+            None => self.error = Some(IKError::NaNPose),
+        }
+        self.pose = pose;
+        self.fallback = fallback;
     }
+}
 
-    fn solve(
-        &self,
-        target: Vec2,
+/// state of `solve_ik`
+/// kept between frames to avoid allocating every frame
+#[derive(Default)]
+pub struct SolveBuffers {
+    /// one buffer per chain of the current batch
+    chains: Vec<ChainBuffers>,
+    /// entities written by the current batch
+    writes: EntityHashSet,
+    /// entities read by the current batch
+    reads: EntityHashSet,
+    /// effectors of the branches of all the `IKTree`s
+    tree_branches: EntityHashSet,
+    /// state of the chains that have an `OutOfReachMode`
+    fallbacks: EntityHashMap<ReachFallback>,
+    /// joints written this frame, see `IKSolvedJoints`
+    solved: EntityHashMap<bool>,
+}
+
+impl SolveBuffers {
+    /// wether solving `constraint` depends on, or changes, what the current batch reads or writes
+    fn conflicts(&self, constraint: &IKConstraint, parents: &Query<&ChildOf>) -> bool {
+        constraint
+            .chain
+            .iter()
+            .any(|e| self.writes.contains(e) || self.reads.contains(e))
+            || constraint
+                .read_entities(parents)
+                .any(|e| self.writes.contains(&e))
+    }
+
+    fn insert(&mut self, constraint: &IKConstraint, parents: &Query<&ChildOf>) {
+        self.writes.extend(constraint.chain.iter().copied());
+        self.reads.extend(constraint.read_entities(parents));
+    }
+
+    /// solve a batch of chains that don't share any entity
+    /// gathering and writing the transforms is done serially, the solving itself is spread on the `ComputeTaskPool`
+    /// chains that fail are skipped, and an `IKFailed` is triggered for them
+    fn solve_batch(
+        &mut self,
+        batch: &[(Entity, &IKConstraint, Vec2)],
         parents: &Query<&ChildOf>,
         transforms: &mut Query<(&mut GlobalTransform, &mut Transform)>,
+        statuses: &mut Query<&mut IKStatus>,
+        commands: &mut Commands,
+        dt: f32,
     ) {
-        let effector = self.chain.last().unwrap();
-
-        for _ in 0..self.iterations {
-            // early break if both effector constraints are within epsilons
-            // or if there are no constrains
-            let effector_gtr = transforms.get(*effector).unwrap().0;
-            if effector_gtr.translation().xy().distance_squared(target)
-                < self.epsilon * self.epsilon
-            {
-                break;
+        if self.chains.len() < batch.len() {
+            self.chains.resize_with(batch.len(), Default::default);
+        }
+        let buffers = &mut self.chains[..batch.len()];
+
+        for ((entity, constraint, _), buffer) in batch.iter().zip(buffers.iter_mut()) {
+            buffer.error = constraint.gather(buffer, true, parents, transforms).err();
+            buffer.fallback = self.fallbacks.remove(entity).unwrap_or_default();
+        }
+
+        let pool = ComputeTaskPool::get_or_init(TaskPool::default);
+        let chunk_size = batch
+            .len()
+            .div_ceil(pool.thread_num())
+            .max(MIN_CHAINS_PER_TASK);
+
+        if chunk_size >= batch.len() {
+            for ((_, constraint, target), buffer) in batch.iter().zip(buffers.iter_mut()) {
+                buffer.solve(constraint, *target, dt);
             }
+        } else {
+            pool.scope(|scope| {
+                for (chains, buffers) in
+                    batch.chunks(chunk_size).zip(buffers.chunks_mut(chunk_size))
+                {
+                    scope.spawn(async move {
+                        for ((_, constraint, target), buffer) in chains.iter().zip(buffers) {
+                            buffer.solve(constraint, *target, dt);
+                        }
+                    });
+                }
+            });
+        }
 
-            self.solve_iteration(target, parents, transforms);
+        for ((entity, constraint, _), buffer) in batch.iter().zip(buffers.iter_mut()) {
+            if constraint.out_of_reach != OutOfReachMode::Stretch {
+                self.fallbacks
+                    .insert(*entity, std::mem::take(&mut buffer.fallback));
+            }
+
+            match buffer.error {
+                Some(error) => commands.trigger(IKFailed {
+                    entity: *entity,
+                    error,
+                }),
+                None => {
+                    constraint.apply_pose(&buffer.pose, 0, parents, transforms, &mut self.solved);
+                    update_status(*entity, buffer.status, statuses, commands);
+                }
+            }
         }
+
+        self.writes.clear();
+        self.reads.clear();
     }
+
+    /// solve all the branches of a tree together
+    /// if a branch fails, the whole tree is skipped, and an `IKFailed` is triggered for the branch
+    /// (or for the tree, if a branch has no `IKConstraint`)
+    fn solve_tree(
+        &mut self,
+        (entity, tree): (Entity, &IKTree),
+        ik_constraints: &Query<(Entity, &IKConstraint)>,
+        parents: &Query<&ChildOf>,
+        transforms: &mut Query<(&mut GlobalTransform, &mut Transform)>,
+        statuses: &mut Query<&mut IKStatus>,
+        commands: &mut Commands,
+    ) {
+        let branches = match tree
+            .branches
+            .iter()
+            .map(|&e| {
+                ik_constraints
+                    .get(e)
+                    .map(|(_, constraint)| constraint)
+                    .map_err(|_| IKError::MissingEntity(e))
+            })
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(branches) => branches,
+            Err(error) => {
+                commands.trigger(IKFailed { entity, error });
+                return;
+            }
+        };
+
+        if !branches.iter().all(|branch| branch.is_mapped()) {
+            return;
+        }
+
+        // link each branch to the first previous branch going through its anchor
+        let links = branches
+            .iter()
+            .enumerate()
+            .map(|(b, branch)| {
+                let anchor = branch.chain[0];
+                branches[..b].iter().enumerate().find_map(|(p, parent)| {
+                    let joint = parent.chain.iter().position(|&e| e == anchor)?;
+                    Some((p, joint))
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // a root whose anchor is a joint of a later branch would never move
+        let unordered = branches.iter().zip(&links).position(|(branch, link)| {
+            let anchor = branch.chain[0];
+            link.is_none()
+                && branches.iter().any(|other| {
+                    other
+                        .chain
+                        .iter()
+                        .position(|&e| e == anchor)
+                        .is_some_and(|joint| joint > 0)
+                })
+        });
+        if let Some(b) = unordered {
+            commands.trigger(IKFailed {
+                entity: tree.branches[b],
+                error: IKError::UnorderedBranch(tree.branches[b]),
+            });
+            return;
+        }
+
+        if self.chains.len() < branches.len() {
+            self.chains.resize_with(branches.len(), Default::default);
+        }
+        let buffers = &mut self.chains[..branches.len()];
+
+        let mut targets = Vec::with_capacity(branches.len());
+        for (b, ((constraint, link), buffer)) in branches
+            .iter()
+            .zip(&links)
+            .zip(buffers.iter_mut())
+            .enumerate()
+        {
+            // the anchor of a sub-branch follows its sub-base instead of its parent
+            let gathered = constraint
+                .gather(buffer, link.is_none(), parents, transforms)
+                .and_then(|_| constraint.target_pos(transforms));
+            match gathered {
+                Ok(target) => targets.push(target),
+                Err(error) => {
+                    commands.trigger(IKFailed {
+                        entity: tree.branches[b],
+                        error,
+                    });
+                    return;
+                }
+            }
+        }
+
+        let mut poses = buffers
+            .iter_mut()
+            .map(|buffer| {
+                buffer.start.clone_from(&buffer.pose);
+                std::mem::take(&mut buffer.pose)
+            })
+            .collect::<Vec<_>>();
+
+        let ik_branches = branches
+            .iter()
+            .zip(&links)
+            .zip(buffers.iter().zip(targets))
+            .map(|((constraint, &parent), (buffer, target))| IKBranch {
+                chain: buffer.chain(constraint),
+                target,
+                parent,
+            })
+            .collect::<Vec<_>>();
+
+        let iterations =
+            FabrikSolver.solve_tree(&ik_branches, &mut poses, tree.iterations, tree.epsilon);
+
+        // blend each branch, from the sub-base its parent branch was blended to
+        let mut new_statuses = Vec::with_capacity(branches.len());
+        for (b, (constraint, link)) in branches.iter().zip(&links).enumerate() {
+            let weight = constraint.weight.clamp(0., 1.);
+            if weight < 1. {
+                poses[b].blend(&buffers[b].start, &buffers[b].lengths, weight);
+            }
+
+            // the anchor of a sub-branch is constrained relatively to its sub-base, like in `solve_tree`
+            let chain = &ik_branches[b].chain;
+            let anchor_dir = match *link {
+                Some((parent, joint)) => {
+                    let offset = poses[parent].positions[joint] - poses[b].positions[0];
+                    for pos in poses[b].positions.iter_mut() {
+                        *pos += offset;
+                    }
+
+                    let rotation =
+                        poses[parent].angles[joint] - ik_branches[parent].chain.rest_angles[joint];
+                    Vec2::from_angle(chain.rest_angles[0] + rotation)
+                }
+                None => chain.anchor_dir(),
+            };
+
+            // branches without target follow their sub-base, their status isn't updated
+            if let Some(target) = ik_branches[b].target {
+                new_statuses.push((
+                    b,
+                    IKStatus::new(chain, &poses[b], anchor_dir, target, iterations),
+                ));
+            }
+        }
+
+        if let Some(b) = poses.iter().position(|pose| !pose.is_finite()) {
+            commands.trigger(IKFailed {
+                entity: tree.branches[b],
+                error: IKError::NaNPose,
+            });
+        } else {
+            for ((constraint, link), pose) in branches.iter().zip(&links).zip(&poses) {
+                // sub-bases are written by their parent branch
+                let from = if link.is_some() { 1 } else { 0 };
+                constraint.apply_pose(pose, from, parents, transforms, &mut self.solved);
+            }
+
+            for (b, status) in new_statuses {
+                update_status(tree.branches[b], status, statuses, commands);
+            }
+        }
+
+        for (buffer, pose) in buffers.iter_mut().zip(poses) {
+            buffer.pose = pose;
+        }
+    }
+}
+
+fn global(
+    transforms: &Query<(&mut GlobalTransform, &mut Transform)>,
+    e: Entity,
+) -> Result<GlobalTransform, IKError> {
+    transforms
+        .get(e)
+        .map(|(gtr, _)| *gtr)
+        .map_err(|_| IKError::MissingEntity(e))
 }
 
+/// under this number of chains, spawning a task costs more than solving them
+const MIN_CHAINS_PER_TASK: usize = 8;
+
+/// solve every chain with a target
+///
+/// chains that don't share any entity are solved in parallel
+/// a chain that shares entities with previous ones (ie: the anchor of an arm being a joint of a spine)
+/// is solved after them, in the order of the query, so that it sees their result
+///
+/// `IKTree`s are solved last, one after the other
+#[allow(clippy::too_many_arguments)]
 pub fn solve_ik(
-    ik_constraints: Query<&IKConstraint>,
+    ik_constraints: Query<(Entity, &IKConstraint)>,
+    trees: Query<(Entity, &IKTree)>,
     parents: Query<&ChildOf>,
     mut transforms: Query<(&mut GlobalTransform, &mut Transform)>,
+    mut statuses: Query<&mut IKStatus>,
+    mut buffers: Local<SolveBuffers>,
+    mut commands: Commands,
+    time: Option<Res<Time>>,
+    solved: Option<ResMut<IKSolvedJoints>>,
 ) {
-    for constraint in ik_constraints.iter() {
-        let target = match constraint.target {
-            IKTarget::None => continue,
-            IKTarget::Pos(target) => target,
-            IKTarget::Entity(target) => {
-                if let Ok((gtr, _)) = transforms.get(target) {
-                    gtr.translation().xy()
-                } else {
-                    warn!("unable to find target entity {}", target);
-                    continue;
-                }
+    // without time, out of reach transitions are instant
+    let dt = time.map_or(f32::INFINITY, |time| time.delta_secs());
+    buffers.solved.clear();
+    buffers
+        .fallbacks
+        .retain(|entity, _| ik_constraints.contains(*entity));
+
+    buffers.tree_branches.clear();
+    for (_, tree) in trees.iter() {
+        buffers.tree_branches.extend(tree.branches.iter().copied());
+    }
+
+    let mut batch = Vec::new();
+
+    for (entity, constraint) in ik_constraints.iter() {
+        // solved with their tree
+        if buffers.tree_branches.contains(&entity) {
+            continue;
+        }
+
+        if !constraint.is_mapped() {
+            continue;
+        }
+
+        let target = match constraint.target_pos(&transforms) {
+            Ok(Some(target)) => target,
+            Ok(None) => continue,
+            Err(error) => {
+                commands.trigger(IKFailed { entity, error });
+                continue;
             }
         };
 
-        constraint.solve(target, &parents, &mut transforms);
+        if constraint.weight <= 0. {
+            continue;
+        }
+
+        if buffers.conflicts(constraint, &parents) {
+            buffers.solve_batch(
+                &batch,
+                &parents,
+                &mut transforms,
+                &mut statuses,
+                &mut commands,
+                dt,
+            );
+            batch.clear();
+        }
+
+        buffers.insert(constraint, &parents);
+        batch.push((entity, constraint, target));
+    }
+
+    buffers.solve_batch(
+        &batch,
+        &parents,
+        &mut transforms,
+        &mut statuses,
+        &mut commands,
+        dt,
+    );
+
+    for tree in trees.iter() {
+        buffers.solve_tree(
+            tree,
+            &ik_constraints,
+            &parents,
+            &mut transforms,
+            &mut statuses,
+            &mut commands,
+        );
+    }
+
+    if let Some(mut solved) = solved {
+        std::mem::swap(&mut solved.joints, &mut buffers.solved);
     }
 }
 
-pub fn map_new_ik(
-    mut ik_constraints: Query<&mut IKConstraint, Added<IKConstraint>>,
-    transforms: Query<(&Transform, &GlobalTransform)>,
-    parents: Query<&ChildOf>,
+/// joints written by the last run of `solve_ik`, for `propagate_ik`
+/// inserted by `IKPlugin`, insert it yourself if you add the systems without it
+#[derive(Resource, Debug, Default)]
+pub struct IKSolvedJoints {
+    /// wether each joint keeps the global transform it was solved to
+    /// false for anchors, which follow their parent
+    joints: EntityHashMap<bool>,
+}
+
+/// update the global transforms of the descendants of the joints solved this frame
+/// (ie: a sprite on a bone, a weapon in a hand), since `solve_ik` runs after the transforms are propagated
+///
+/// solved joints keep their solved pose, everything else follows its parent
+/// including anchors, and the joints of chains that weren't solved
+pub fn propagate_ik(
+    solved: Option<Res<IKSolvedJoints>>,
+    children: Query<&Children>,
+    mut transforms: Query<(&mut GlobalTransform, &mut Transform)>,
 ) {
-    for mut ik in &mut ik_constraints {
-        ik.anchor_parent_rest_rot = match parents.get(*ik.chain.first().unwrap()) {
-            Ok(parent) => {
-                transforms
-                    .get(parent.parent())
-                    .unwrap()
-                    .1
-                    .rotation()
-                    .to_euler(EulerRot::ZXY)
-                    .0
-            }
-            Err(_) => 0.0,
+    let Some(solved) = solved else {
+        return;
+    };
+
+    for &e in solved.joints.keys() {
+        let (Ok((gtr, _)), Ok(e_children)) = (transforms.get(e), children.get(e)) else {
+            continue;
         };
+        let gtr = *gtr;
 
-        // cache all the transforms
-        // it might be useless perf wise, but it avoid a lot of unwraps
-        match ik
-            .chain
-            .iter()
-            .map(|&e| Ok(transforms.get(e)?))
-            .into_iter()
-            .collect::<Result<Vec<_>, QueryEntityError>>()
-        {
-            Ok(transforms) => {
-                for i in 0..ik.chain.len() {
-                    let e = ik.chain[i];
-                    let (_, gtr) = transforms[i];
+        for child in e_children.iter() {
+            propagate(child, gtr, &children, &solved.joints, &mut transforms);
+        }
+    }
+}
 
-                    ik.rest_data.insert(e, gtr.rotation());
+fn propagate(
+    entity: Entity,
+    parent_gtr: GlobalTransform,
+    children: &Query<&Children>,
+    solved: &EntityHashMap<bool>,
+    transforms: &mut Query<(&mut GlobalTransform, &mut Transform)>,
+) {
+    let Ok((mut gtr, mut tr)) = transforms.get_mut(entity) else {
+        return;
+    };
+
+    // a solved joint keeps its pose, only its local transform is updated for its new parent
+    // (ie: a hand below a twist bone), its descendants are propagated from it
+    if solved.get(&entity) == Some(&true) {
+        *tr = gtr.reparented_to(&parent_gtr);
+        return;
+    }
 
-                    if let Some(prev_i) = i.checked_sub(1) {
-                        let prev_e = ik.chain[prev_i];
-                        let (_, prev_gtr) = transforms[prev_i];
+    *gtr = parent_gtr.mul_transform(*tr);
+    let gtr = *gtr;
 
-                        let dist = gtr.translation().xy().distance(prev_gtr.translation().xy());
-                        ik.bone_data.insert((e, prev_e), Bone::new(dist));
-                        ik.bone_data.insert((prev_e, e), Bone::new(dist));
-                    }
+    if let Ok(entity_children) = children.get(entity) {
+        for child in entity_children.iter() {
+            propagate(child, gtr, children, solved, transforms);
+        }
+    }
+}
 
-                    match i {
-                        // we are at the anchor
-                        // take the direction to the next joint as the angle
-                        i if i == 0 => {
-                            let anchor_gtr = transforms[0].1;
-                            let anchor_child_gtr = transforms[1].1;
-                            let dir =
-                                anchor_child_gtr.translation().xy() - anchor_gtr.translation().xy();
-                            ik.joint_data.insert(e, JointRest::new(dir.to_angle()));
-                        }
-                        _ => {
-                            let (_, prev_gtr) = transforms[i - 1];
+/// compute the rest data of the new chains
+/// and recompute it for the chains that were edited, reparented, or asked to with `IKConstraint::remap`
+/// their current pose becomes their rest pose
+///
+/// chains that can't be mapped won't be solved, and an `IKFailed` is triggered for them
+pub fn map_new_ik(
+    mut ik_constraints: Query<(Entity, &mut IKConstraint)>,
+    transforms: Query<(&Transform, &GlobalTransform)>,
+    parents: Query<&ChildOf>,
+    reparented: Query<(), Changed<ChildOf>>,
+    mut unparented: RemovedComponents<ChildOf>,
+    mut commands: Commands,
+) {
+    let unparented = unparented.read().collect::<EntityHashSet>();
+
+    for (entity, mut ik) in &mut ik_constraints {
+        let changed = ik.mapped_chain.as_ref() != Some(&ik.chain)
+            || ik
+                .chain
+                .iter()
+                .any(|&e| reparented.contains(e) || unparented.contains(&e));
+        if !changed {
+            continue;
+        }
 
-                            let dir = gtr.translation().xy() - prev_gtr.translation().xy();
+        ik.mapped_chain = Some(ik.chain.clone());
+        ik.bone_data.clear();
+        ik.joint_data.clear();
+        ik.rest_data.clear();
 
-                            ik.joint_data.insert(e, JointRest::new(dir.to_angle()));
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                warn!("unable to find element of IK chain {}", e);
-                continue;
-            }
+        let mapped = ik.map(&transforms, &parents);
+        ik.mapped = mapped.is_ok();
+        if let Err(error) = mapped {
+            commands.trigger(IKFailed { entity, error });
         }
     }
 }
@@ -552,24 +1468,16 @@ fn debug_ik(
 ) {
     let Some(debug) = debug else { return };
 
-    for constraint in ik_constraints.iter() {
-        let anchor = constraint.chain.first().unwrap();
-        let anchor_dir = match parents.get(*anchor) {
-            Ok(parent) => {
-                let parent_z_rot = transforms
-                    .get(parent.parent())
-                    .unwrap()
-                    .rotation()
-                    .to_euler(EulerRot::ZXY)
-                    .0;
+    for constraint in ik_constraints.iter().filter(|c| c.is_mapped()) {
+        let Some((_, anchor_dir)) = constraint.anchor_frame(&transforms, &parents) else {
+            continue;
+        };
 
-                Vec2::from_angle(
-                    constraint.joint_data.get(anchor).unwrap().angle + parent_z_rot
-                        - constraint.anchor_parent_rest_rot,
-                )
+        if debug.reach {
+            if let Some(reach) = constraint.reach(&transforms, &parents) {
+                reach.draw(&mut gizmos, Color::srgb(0., 0.5, 1.));
             }
-            Err(_) => Vec2::from_angle(constraint.joint_data.get(anchor).unwrap().angle),
-        };
+        }
 
         let mut prev_dir = anchor_dir;
 
@@ -584,14 +1492,14 @@ fn debug_ik(
             if let Some(joint) = debug.joints {
                 gizmos.circle_2d(gtr.translation().xy(), joint, Color::srgb(0., 1., 0.));
             }
-            let Some(next) = next else {
+            let Some(Ok(next_gtr)) = next.map(|next| transforms.get(*next)) else {
                 continue;
             };
 
             if debug.bones {
                 gizmos.line_2d(
                     gtr.translation().xy(),
-                    transforms.get(*next).unwrap().translation().xy(),
+                    next_gtr.translation().xy(),
                     Color::srgb(0., 1., 0.),
                 );
             }
@@ -619,9 +1527,7 @@ fn debug_ik(
                     Color::srgb(1., 0., 0.),
                 );
 
-                let bone_dir = (transforms.get(*next).unwrap().translation().xy()
-                    - gtr.translation().xy())
-                .normalize();
+                let bone_dir = (next_gtr.translation().xy() - gtr.translation().xy()).normalize();
                 let angle_offset = prev_dir.angle_to(bone_dir);
 
                 prev_dir = bone_dir.normalize();
@@ -636,7 +1542,6 @@ fn debug_ik(
                             * Rot2::radians(diff_from_rest)
                             //              -angle_offset to remove the impact of the rotation of the bone from the arc (which shouldnt turn with the bone)
                             * Rot2::radians(-angle_offset),
-                        ..default()
                     },
                     cw + ccw,
                     len,
@@ -646,3 +1551,186 @@ fn debug_ik(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            IKPlugin::default().with_debug(false),
+        ));
+        app
+    }
+
+    /// every `IKFailed` triggered so far
+    #[derive(Resource, Default)]
+    struct Failures(Vec<(Entity, IKError)>);
+
+    fn record_failures(app: &mut App) {
+        app.init_resource::<Failures>().add_observer(
+            |failed: On<IKFailed>, mut failures: ResMut<Failures>| {
+                failures.0.push((failed.entity, failed.error));
+            },
+        );
+    }
+
+    fn spawn(app: &mut App, translation: Vec2, parent: Option<Entity>) -> Entity {
+        let mut entity = app
+            .world_mut()
+            .spawn(Transform::from_translation(translation.extend(0.)));
+        if let Some(parent) = parent {
+            entity.insert(ChildOf(parent));
+        }
+        entity.id()
+    }
+
+    fn global(app: &App, e: Entity) -> GlobalTransform {
+        *app.world().get::<GlobalTransform>(e).unwrap()
+    }
+
+    /// wether the global transform of `child` is its local one, relative to `parent`
+    fn follows(app: &App, child: Entity, parent: Entity) -> bool {
+        let tr = *app.world().get::<Transform>(child).unwrap();
+        let expected = global(app, parent).mul_transform(tr);
+        let gtr = global(app, child);
+        gtr.translation().distance(expected.translation()) < 1e-3
+            && gtr.rotation().angle_between(expected.rotation()) < 1e-3
+    }
+
+    #[test]
+    fn propagates_to_children_of_solved_joints() {
+        let mut app = app();
+        let anchor = spawn(&mut app, Vec2::ZERO, None);
+        let joint = spawn(&mut app, Vec2::X * 10., Some(anchor));
+        let twist = spawn(&mut app, Vec2::X * 5., Some(joint));
+        let hand = spawn(&mut app, Vec2::X * 5., Some(twist));
+        let joint_sprite = spawn(&mut app, Vec2::Y * 3., Some(joint));
+        let hand_sprite = spawn(&mut app, Vec2::Y * 2., Some(hand));
+
+        let target = Vec2::new(5., 15.);
+        app.world_mut().entity_mut(hand).insert(
+            IKConstraint::new(vec![anchor, joint, hand]).with_target(IKTarget::Pos(target)),
+        );
+        for _ in 0..3 {
+            app.update();
+        }
+
+        // the hand keeps its solved pose below the twist bone
+        assert!(global(&app, hand).translation().xy().distance(target) < 1.);
+        assert!(follows(&app, twist, joint));
+        assert!(follows(&app, hand, twist));
+        assert!(follows(&app, joint_sprite, joint));
+        assert!(follows(&app, hand_sprite, hand));
+    }
+
+    #[test]
+    fn idle_chain_follows_moved_parent() {
+        let mut app = app();
+        let spine = [
+            spawn(&mut app, Vec2::ZERO, None),
+            spawn(&mut app, Vec2::Y * 10., None),
+            spawn(&mut app, Vec2::Y * 10., None),
+        ];
+        app.world_mut()
+            .entity_mut(spine[1])
+            .insert(ChildOf(spine[0]));
+        app.world_mut()
+            .entity_mut(spine[2])
+            .insert(ChildOf(spine[1]));
+        let shoulder = spawn(&mut app, Vec2::X * 10., Some(spine[1]));
+        let arm = spawn(&mut app, Vec2::Y * 5., Some(shoulder));
+        let hand = spawn(&mut app, Vec2::X * 10., Some(arm));
+
+        app.world_mut().entity_mut(spine[2]).insert(
+            IKConstraint::new(spine.to_vec()).with_target(IKTarget::Pos(Vec2::new(-10., 10.))),
+        );
+        app.world_mut()
+            .entity_mut(hand)
+            .insert(IKConstraint::new(vec![arm, hand]));
+        for _ in 0..5 {
+            app.update();
+        }
+
+        // the spine moved the shoulder, and the arm, which has no target, came along
+        assert!(
+            global(&app, shoulder)
+                .translation()
+                .xy()
+                .distance(Vec2::new(10., 10.))
+                > 1.
+        );
+        let arm_tr = app.world().get::<Transform>(arm).unwrap();
+        assert!(arm_tr.translation.xy().distance(Vec2::Y * 5.) < 1e-3);
+        assert!(follows(&app, arm, shoulder));
+        assert!(follows(&app, hand, arm));
+    }
+
+    #[test]
+    fn maps_empty_chains() {
+        let mut app = app();
+        record_failures(&mut app);
+        let entity = app.world_mut().spawn(IKConstraint::new(vec![])).id();
+
+        for _ in 0..3 {
+            app.update();
+        }
+        let failures = &app.world().resource::<Failures>().0;
+        assert_eq!(failures, &[(entity, IKError::ChainTooShort(0))]);
+
+        app.world_mut()
+            .get_mut::<IKConstraint>(entity)
+            .unwrap()
+            .remap();
+        app.update();
+        assert_eq!(app.world().resource::<Failures>().0.len(), 2);
+    }
+
+    #[test]
+    fn reports_invalid_chains() {
+        let mut app = app();
+        record_failures(&mut app);
+
+        // a -> b -> c nested, the others unparented
+        let a = spawn(&mut app, Vec2::ZERO, None);
+        let b = spawn(&mut app, Vec2::X * 10., Some(a));
+        let c = spawn(&mut app, Vec2::X * 10., Some(b));
+        let loose = spawn(&mut app, Vec2::new(30., 0.), None);
+        let same = [
+            spawn(&mut app, Vec2::Y * 50., None),
+            spawn(&mut app, Vec2::Y * 50., None),
+            spawn(&mut app, Vec2::new(10., 50.), None),
+        ];
+        // siblings are fine
+        let root = spawn(&mut app, Vec2::Y * 100., None);
+        let siblings = [0., 10., 20.].map(|x| spawn(&mut app, Vec2::X * x, Some(root)));
+
+        let cases = [
+            (vec![a], IKError::ChainTooShort(1)),
+            (vec![a, b, a], IKError::DuplicateEntity(a)),
+            (vec![a, c, b], IKError::Disconnected(c, b)),
+            (vec![a, b, loose], IKError::Disconnected(b, loose)),
+            (same.to_vec(), IKError::ZeroLengthBone(same[0], same[1])),
+        ];
+        let mut expected = cases
+            .into_iter()
+            .map(|(chain, error)| (app.world_mut().spawn(IKConstraint::new(chain)).id(), error))
+            .collect::<Vec<_>>();
+
+        let invalid = IKConstraint::new(vec![a, b, c])
+            .with_joint_constraints(vec![(b, JointConstraint::new(4., 0.))]);
+        let entity = app.world_mut().spawn(invalid).id();
+        expected.push((entity, IKError::InvalidJointConstraint(b)));
+        app.world_mut().spawn(IKConstraint::new(siblings.to_vec()));
+
+        app.update();
+        let failures = &app.world().resource::<Failures>().0;
+        assert_eq!(failures.len(), expected.len(), "{failures:?}");
+        for failure in expected {
+            assert!(failures.contains(&failure), "{failure:?}");
+        }
+    }
+}
@@ -1,4 +1,18 @@
+mod animation;
+mod bake;
+mod chain;
+mod error;
 mod ik;
+mod reach;
+mod solver;
+mod status;
 
-pub use ik::{map_new_ik, solve_ik, Bone, JointRest};
-pub use ik::{DebugIK, IKConstraint, IKPlugin, IKTarget, JointConstraint};
+pub use animation::{IKTargetOffsetProperty, IKTargetPosProperty, IKWeightProperty};
+pub use bake::{record_ik, IKRecording};
+pub use chain::{AnchorMode, IKBranch, IKChain, IKPose};
+pub use error::{IKError, IKFailed};
+pub use ik::{map_new_ik, propagate_ik, solve_ik, Bone, IKSolvedJoints, JointRest};
+pub use ik::{DebugIK, IKConstraint, IKPlugin, IKSystems, IKTarget, IKTree, JointConstraint};
+pub use reach::{IKReach, OutOfReachMode, ReachFallback};
+pub use solver::{BendDirection, CcdSolver, FabrikSolver, IKSolver, JacobianSolver, TwoBoneSolver};
+pub use status::{IKLost, IKReached, IKStatus, IKUnreachable};
@@ -0,0 +1,300 @@
+use bevy::prelude::*;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use crate::{IKChain, IKPose, JointConstraint};
+
+/// approximate region the effector of a chain can reach, an annular sector around the anchor
+///
+/// the reach is exact without joint constraints
+/// with them, the extreme poses are approximated by folding every joint to the same limit
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct IKReach {
+    /// absolute position of the anchor
+    pub center: Vec2,
+
+    /// min distance between the anchor and the effector
+    pub min: f32,
+
+    /// max distance between the anchor and the effector
+    pub max: f32,
+
+    /// absolute angle of the clockwise edge of the sector
+    pub start: f32,
+
+    /// angle of the sector, counter clockwise from `start`
+    /// `TAU` when the effector can go all around the anchor
+    pub sweep: f32,
+}
+
+impl IKReach {
+    /// region reachable by a chain anchored at `center`
+    /// `anchor_dir` is the reference direction of the anchor's constraint
+    /// `lengths` and `constraints` are indexed like in `IKChain`
+    pub fn new(
+        center: Vec2,
+        anchor_dir: Vec2,
+        lengths: &[f32],
+        constraints: &[Option<JointConstraint>],
+    ) -> Self {
+        let straight = effector(lengths, constraints, |min, max| 0f32.clamp(min, max));
+        let folded = [
+            effector(lengths, constraints, |_, max| max),
+            effector(lengths, constraints, |min, _| min),
+        ];
+
+        let max = straight.length();
+        let free = constraints[1..lengths.len()].iter().all(Option::is_none);
+        let min = match free {
+            // the longest bone can't be folded back further than the others are long
+            true => {
+                let longest = lengths.iter().copied().fold(0., f32::max);
+                (2. * longest - lengths.iter().sum::<f32>()).max(0.)
+            }
+            false => folded.iter().map(|p| p.length()).fold(max, f32::min),
+        };
+
+        let (start, sweep) = match constraints[0] {
+            Some(JointConstraint { ccw, cw }) => {
+                // the effector can be on either side of the first bone
+                let angles = [straight, folded[0], folded[1]].map(Vec2::to_angle);
+                let lo = angles.iter().copied().fold(0., f32::min);
+                let hi = angles.iter().copied().fold(0., f32::max);
+                (
+                    anchor_dir.to_angle() - cw + lo,
+                    (cw + ccw + hi - lo).min(TAU),
+                )
+            }
+            None => (0., TAU),
+        };
+
+        Self {
+            center,
+            min,
+            max,
+            start,
+            sweep,
+        }
+    }
+
+    /// wether `point` is in the region
+    pub fn contains(&self, point: Vec2) -> bool {
+        let to_point = point - self.center;
+        let dist = to_point.length();
+        if dist < self.min || dist > self.max {
+            return false;
+        }
+
+        self.sweep >= TAU || (to_point.to_angle() - self.start).rem_euclid(TAU) <= self.sweep
+    }
+
+    /// closest point of the region to `point`
+    pub fn clamp(&self, point: Vec2) -> Vec2 {
+        let to_point = point - self.center;
+        let dist = to_point.length().clamp(self.min, self.max);
+
+        if self.sweep >= TAU || (to_point.to_angle() - self.start).rem_euclid(TAU) <= self.sweep {
+            return self.center + to_point.normalize_or(Vec2::from_angle(self.start)) * dist;
+        }
+
+        // outside of the sector, the closest point is on one of its edges
+        [self.start, self.start + self.sweep]
+            .map(|angle| {
+                let dir = Vec2::from_angle(angle);
+                self.center + dir * to_point.dot(dir).clamp(self.min, self.max)
+            })
+            .into_iter()
+            .min_by(|a, b| {
+                a.distance_squared(point)
+                    .total_cmp(&b.distance_squared(point))
+            })
+            .unwrap()
+    }
+
+    /// draw the outline of the region
+    pub fn draw(&self, gizmos: &mut Gizmos, color: impl Into<Color>) {
+        let color = color.into();
+
+        // FRAC_PI_2 bc the arc is drawn starting from Vec2::Y
+        let isometry = Isometry2d::new(self.center, Rot2::radians(self.start - FRAC_PI_2));
+        gizmos.arc_2d(isometry, self.sweep, self.max, color);
+        if self.min > 0. {
+            gizmos.arc_2d(isometry, self.sweep, self.min, color);
+        }
+
+        if self.sweep < TAU {
+            for angle in [self.start, self.start + self.sweep] {
+                let dir = Vec2::from_angle(angle);
+                gizmos.line_2d(
+                    self.center + dir * self.min,
+                    self.center + dir * self.max,
+                    color,
+                );
+            }
+        }
+    }
+}
+
+/// position of the effector relative to the anchor, with the first bone along X
+/// every joint after the anchor takes the angle `pick(min, max)` within its constraint
+fn effector(
+    lengths: &[f32],
+    constraints: &[Option<JointConstraint>],
+    pick: impl Fn(f32, f32) -> f32,
+) -> Vec2 {
+    let mut dir = Vec2::X;
+    let mut pos = Vec2::ZERO;
+
+    for (i, &length) in lengths.iter().enumerate() {
+        if i > 0 {
+            let (min, max) = match constraints[i] {
+                Some(JointConstraint { ccw, cw }) => (-cw, ccw),
+                None => (-PI, PI),
+            };
+            dir = Vec2::from_angle(pick(min, max)).rotate(dir);
+        }
+        pos += dir * length;
+    }
+
+    pos
+}
+
+/// what a chain does when its target is out of reach
+///
+/// switching to and from the out of reach behavior takes `transition` seconds, so that limbs don't snap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect)]
+pub enum OutOfReachMode {
+    /// the chain points straight at the target
+    #[default]
+    Stretch,
+    /// the target is moved to the closest point the chain can reach (see `IKReach`)
+    Clamp { transition: f32 },
+    /// the chain keeps the last pose it was solved to with its target in reach
+    KeepLast { transition: f32 },
+    /// the chain goes back to its rest pose
+    Rest { transition: f32 },
+}
+
+impl OutOfReachMode {
+    fn transition(self) -> f32 {
+        match self {
+            OutOfReachMode::Stretch => 0.,
+            OutOfReachMode::Clamp { transition }
+            | OutOfReachMode::KeepLast { transition }
+            | OutOfReachMode::Rest { transition } => transition,
+        }
+    }
+}
+
+/// state of the `OutOfReachMode` of a chain, kept between its solves (see `IKChain::solve_with_fallback`)
+#[derive(Clone, Debug, Default)]
+pub struct ReachFallback {
+    /// how much the out of reach behavior is applied
+    /// between 0 (target in reach) and 1 (fully applied)
+    amount: f32,
+
+    /// angles of the joints of the last pose solved with the target in reach
+    /// relative to the rest angle of the anchor, so that it follows the anchor's parent
+    last_valid: Vec<f32>,
+}
+
+impl ReachFallback {
+    /// move `amount` towards wether the target is out of reach, over the transition of `mode`
+    pub(crate) fn update(&mut self, mode: OutOfReachMode, out_of_reach: bool, dt: f32) {
+        let to = if out_of_reach { 1. } else { 0. };
+
+        let transition = mode.transition();
+        self.amount = match transition > 0. {
+            true => self.amount + (to - self.amount).clamp(-dt / transition, dt / transition),
+            false => to,
+        };
+    }
+
+    /// target the chain is solved towards
+    pub(crate) fn target(&self, mode: OutOfReachMode, reach: &IKReach, target: Vec2) -> Vec2 {
+        match mode {
+            OutOfReachMode::Clamp { .. } if self.amount > 0. => {
+                target.lerp(reach.clamp(target), self.amount)
+            }
+            _ => target,
+        }
+    }
+
+    /// keep `pose` as the last valid one
+    pub(crate) fn store(&mut self, chain: &IKChain, pose: &IKPose) {
+        let anchor = chain.rest_angles[0];
+        self.last_valid.clear();
+        self.last_valid
+            .extend(pose.angles.iter().map(|angle| angle - anchor));
+    }
+
+    /// blend the solved `pose` towards the fallback pose of `mode`
+    pub(crate) fn apply(&self, mode: OutOfReachMode, chain: &IKChain, pose: &mut IKPose) {
+        if self.amount <= 0. {
+            return;
+        }
+
+        let anchor = chain.rest_angles[0];
+        let fallback = match mode {
+            OutOfReachMode::KeepLast { .. } if self.last_valid.len() == chain.len() => {
+                let angles = self.last_valid.iter().map(|angle| angle + anchor).collect();
+                IKPose::from_angles(pose.positions[0], angles, chain.lengths)
+            }
+            OutOfReachMode::KeepLast { .. } | OutOfReachMode::Rest { .. } => {
+                chain.rest_pose(pose.positions[0])
+            }
+            OutOfReachMode::Stretch | OutOfReachMode::Clamp { .. } => return,
+        };
+
+        pose.blend(&fallback, chain.lengths, 1. - self.amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::tests::{chain, straight};
+    use crate::FabrikSolver;
+
+    #[test]
+    fn contains_and_clamp() {
+        let lengths = [10., 5.];
+        let reach = IKReach::new(Vec2::ZERO, Vec2::X, &lengths, &[None; 3]);
+        assert_eq!((reach.min, reach.max, reach.sweep), (5., 15., TAU));
+
+        assert!(reach.contains(Vec2::new(0., 10.)));
+        assert!(!reach.contains(Vec2::new(2., 2.)));
+        assert!(!reach.contains(Vec2::new(20., 0.)));
+
+        assert_eq!(reach.clamp(Vec2::new(0., 10.)), Vec2::new(0., 10.));
+        assert!(reach.clamp(Vec2::new(3., 0.)).distance(Vec2::X * 5.) < 1e-4);
+        assert!(reach.clamp(Vec2::new(0., -30.)).distance(Vec2::Y * -15.) < 1e-4);
+
+        // the anchor can only turn a quarter turn counter clockwise from X
+        let constraints = [Some(JointConstraint::new(FRAC_PI_2, 0.)), None, None];
+        let reach = IKReach::new(Vec2::ZERO, Vec2::X, &lengths, &constraints);
+        assert!(reach.contains(Vec2::new(10., 10.)));
+        assert!(!reach.contains(Vec2::new(-14., -1.)));
+
+        // a point outside of the sector goes to its closest edge
+        assert!(reach.clamp(Vec2::new(12., -3.)).distance(Vec2::X * 12.) < 1e-4);
+        assert!(reach.clamp(Vec2::new(-3., 20.)).distance(Vec2::Y * 15.) < 1e-4);
+    }
+
+    #[test]
+    fn rest_fallback_keeps_bent_rest_pose() {
+        // rest pose (0, 0), (10, 0), (10, 10)
+        let chain = chain(&[10., 10.], &[0., 0., FRAC_PI_2], &[None; 3]);
+        let mut pose = straight(&[10., 10.]);
+
+        let mode = OutOfReachMode::Rest { transition: 0. };
+        let mut fallback = ReachFallback::default();
+        let target = Vec2::new(50., 0.);
+        chain.solve_with_fallback(&FabrikSolver, &mut pose, target, mode, &mut fallback, 0.);
+
+        let expected = [Vec2::ZERO, Vec2::new(10., 0.), Vec2::new(10., 10.)];
+        for (p, e) in pose.positions.iter().zip(expected) {
+            assert!(p.distance(e) < 1e-4, "{:?}", pose.positions);
+        }
+        assert_eq!(pose.angles, chain.rest_angles);
+    }
+}
@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+use std::fmt::Debug;
+
+use crate::{IKChain, IKPose};
+
+mod ccd;
+mod fabrik;
+mod jacobian;
+mod two_bone;
+
+pub use ccd::CcdSolver;
+pub use fabrik::FabrikSolver;
+pub use jacobian::JacobianSolver;
+pub use two_bone::{BendDirection, TwoBoneSolver};
+
+/// algorithm used to move a chain towards its target
+///
+/// implement this trait to plug your own algorithm into an `IKConstraint` with `IKConstraint::with_solver`
+/// solvers only work on plain data, they never touch the ECS
+pub trait IKSolver: Debug + Send + Sync + 'static {
+    /// move the joints of `pose` so that the effector of `chain` reaches `target`
+    /// `pose` holds the current pose of the chain when called, and must hold the solved one when returning
+    /// returns the number of iterations used
+    fn solve(&self, chain: &IKChain, pose: &mut IKPose, target: Vec2) -> usize;
+}
+
+impl Default for Box<dyn IKSolver> {
+    fn default() -> Self {
+        Box::new(FabrikSolver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::tests::{bone_lengths, chain, straight, within_constraints};
+    use crate::JointConstraint;
+    use std::f32::consts::FRAC_PI_4;
+
+    #[test]
+    fn reaches_target() {
+        let lengths = [10., 10.];
+        let chain = chain(&lengths, &[0.; 3], &[None; 3]);
+        let target = Vec2::new(12., 9.);
+        let solvers: [&dyn IKSolver; 4] = [
+            &FabrikSolver,
+            &CcdSolver,
+            &JacobianSolver::default(),
+            &TwoBoneSolver::default(),
+        ];
+
+        for solver in solvers {
+            let pose = chain.solve(solver, straight(&lengths), target);
+
+            assert!(
+                pose.positions[2].distance(target) < chain.epsilon,
+                "{solver:?}"
+            );
+            for length in bone_lengths(&pose) {
+                assert!((length - 10.).abs() < 1e-3, "{solver:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_joint_constraints() {
+        let lengths = [10., 10.];
+        let limit = Some(JointConstraint::new(FRAC_PI_4, FRAC_PI_4));
+        let constraints = [limit, limit, None];
+        let chain = chain(&lengths, &[0.; 3], &constraints);
+        let solvers: [&dyn IKSolver; 3] = [
+            &CcdSolver,
+            &JacobianSolver::default(),
+            &TwoBoneSolver::default(),
+        ];
+
+        // reaching it needs both joints bent further than their constraints
+        for solver in solvers {
+            let pose = chain.solve(solver, straight(&lengths), Vec2::new(5., 5.));
+
+            assert!(within_constraints(&chain, &pose), "{solver:?}");
+            assert!(chain.at_limit(&pose, chain.anchor_dir()), "{solver:?}");
+        }
+    }
+}
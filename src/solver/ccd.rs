@@ -0,0 +1,91 @@
+use bevy::prelude::*;
+
+use super::IKSolver;
+use crate::{IKChain, IKPose};
+
+/// Cyclic Coordinate Descent
+///
+/// rotates one joint at a time, from the effector to the anchor, so that the effector gets closer to the target
+/// the joint constraints are applied at every step, so they hold at every joint and bones never change length
+/// the last bone is rotated towards the effector's target angle, if any
+#[derive(Clone, Copy, Debug, Default, Reflect)]
+pub struct CcdSolver;
+
+impl CcdSolver {
+    /// rotate the bone starting at joint `i` so that the effector points towards `target`
+    /// `prev_dir` is the absolute direction of the previous bone (or of the anchor at rest)
+    fn rotate_joint(
+        chain: &IKChain,
+        i: usize,
+        prev_dir: Vec2,
+        target: Vec2,
+        positions: &mut [Vec2],
+    ) {
+        let pivot = positions[i];
+        let effector = *positions.last().unwrap();
+
+        let delta = (effector - pivot).angle_to(target - pivot);
+        if delta.is_nan() {
+            return;
+        }
+
+        let dir = (positions[i + 1] - pivot).normalize();
+        let mut wanted = Vec2::from_angle(delta).rotate(dir);
+        if i == positions.len() - 2 {
+            wanted = chain.oriented_bone_dir(wanted);
+        }
+
+        // clamp the new direction of the bone relatively to the previous one
+        let angle = chain.constrain(i, prev_dir.angle_to(wanted));
+        let new_dir = Vec2::from_angle(angle).rotate(prev_dir);
+        let rotation = Vec2::from_angle(dir.angle_to(new_dir));
+
+        // rotate the rest of the chain around the joint
+        for pos in positions[i + 1..].iter_mut() {
+            *pos = pivot + rotation.rotate(*pos - pivot);
+        }
+    }
+}
+
+impl IKSolver for CcdSolver {
+    fn solve(&self, chain: &IKChain, pose: &mut IKPose, target: Vec2) -> usize {
+        chain.float_anchor(pose, target);
+
+        let anchor_dir = chain.anchor_dir();
+        let positions = &mut pose.positions;
+        let last = positions.len() - 1;
+
+        chain.bend_to_pole(positions);
+
+        let mut iterations = 0;
+        while iterations < chain.iterations {
+            // early break if the effector is within epsilon of the target
+            if chain.solved(positions, target) {
+                break;
+            }
+            iterations += 1;
+
+            // iter from the joint closest to the effector to the anchor
+            for i in (0..last).rev() {
+                let prev_dir = match i {
+                    0 => anchor_dir,
+                    _ => (positions[i] - positions[i - 1]).normalize(),
+                };
+                Self::rotate_joint(chain, i, prev_dir, target, positions);
+            }
+        }
+
+        let positions = &pose.positions;
+
+        for i in 0..last {
+            pose.angles[i] = (positions[i + 1] - positions[i]).to_angle();
+        }
+
+        let prev_dir = Vec2::from_angle(pose.angles[last - 1]);
+        pose.angles[last] = chain
+            .effector_dir(prev_dir, positions[last], target)
+            .to_angle();
+
+        iterations
+    }
+}
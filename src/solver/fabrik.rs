@@ -0,0 +1,289 @@
+use bevy::prelude::*;
+
+use super::IKSolver;
+use crate::{IKBranch, IKChain, IKPose};
+
+/// Forward And Backward Reaching Inverse Kinematics
+///
+/// description [here](http://www.andreasaristidou.com/FABRIK.html)
+///
+/// this is the default solver
+#[derive(Clone, Copy, Debug, Default, Reflect)]
+pub struct FabrikSolver;
+
+impl FabrikSolver {
+    /// bring joint `i - 1` at the right distance of joint `i`
+    /// the last bone is also oriented for the effector to reach its target angle
+    fn pull(chain: &IKChain, positions: &mut [Vec2], i: usize) {
+        let e1_pos = positions[i];
+        let e0_pos = positions[i - 1];
+        let mut dir = (e1_pos - e0_pos).normalize();
+        if i == positions.len() - 1 {
+            dir = chain.oriented_bone_dir(dir);
+        }
+        positions[i - 1] = e1_pos - dir * chain.lengths[i - 1];
+    }
+
+    /// pull the chain to the anchor
+    /// while respecting the length and angle constraints
+    /// iter from anchor to effector
+    /// e0 will pull e1
+    /// and rotate e0 accordingly
+    ///
+    /// `anchor_dir` is the reference direction of the anchor's angle constraint
+    fn forward(chain: &IKChain, pose: &mut IKPose, anchor_dir: Vec2, target: Vec2) {
+        let positions = &mut pose.positions;
+        let last = positions.len() - 1;
+
+        let mut prev_dir = anchor_dir;
+
+        for i in 0..last {
+            let e0_pos = positions[i];
+            let e1_pos = positions[i + 1];
+
+            let dir = (e1_pos - e0_pos).normalize();
+
+            let angle = chain.constrain(i, prev_dir.angle_to(dir));
+            let dir = (Mat2::from_angle(angle) * prev_dir).normalize();
+
+            positions[i + 1] = e0_pos + dir * chain.lengths[i];
+            pose.angles[i] = dir.to_angle();
+
+            prev_dir = dir;
+        }
+
+        // restrain the effector's angle
+        // since it doesnt happen in the loop above
+        pose.angles[last] = chain
+            .effector_dir(prev_dir, positions[last], target)
+            .to_angle();
+    }
+
+    fn solve_iteration(chain: &IKChain, pose: &mut IKPose, target: Vec2) {
+        let last = pose.positions.len() - 1;
+        let anchor_pos = pose.positions[0];
+
+        // bring the effector to the target position
+        pose.positions[last] = target;
+
+        // pull the chain to the effector
+        // while respecting the length constraints
+        // iter from effector to anchor
+        // e1 will pull e0
+        for i in (1..=last).rev() {
+            Self::pull(chain, &mut pose.positions, i);
+        }
+
+        // bring the anchor back to its original position
+        // a floating anchor was already moved by `float_anchor`
+        pose.positions[0] = anchor_pos;
+
+        // use the anchor's (potentially relative, if it has a parent) rotation as the original direction
+        // to also apply the angle constraint on the anchor rotation
+        Self::forward(chain, pose, chain.anchor_dir(), target);
+    }
+
+    /// multiple end effectors FABRIK, solving the branches of a tree together
+    ///
+    /// on the backward pass, each sub-base is placed at the average of the positions requested by its branches
+    /// on the forward pass, each branch starts from the solved position of its sub-base
+    /// the roots (branches without parent) don't move, whatever their `AnchorMode`
+    ///
+    /// `poses` is indexed like `branches`, and parents must come before their children
+    /// returns the number of iterations used
+    pub fn solve_tree(
+        &self,
+        branches: &[IKBranch],
+        poses: &mut [IKPose],
+        iterations: usize,
+        epsilon: f32,
+    ) -> usize {
+        debug_assert_eq!(branches.len(), poses.len());
+
+        let roots = poses.iter().map(|p| p.positions[0]).collect::<Vec<_>>();
+
+        for (branch, pose) in branches.iter().zip(poses.iter_mut()) {
+            branch.chain.bend_to_pole(&mut pose.positions);
+        }
+
+        // a branch without any target in its subtree doesn't pull its sub-base
+        let mut active = branches
+            .iter()
+            .map(|b| b.target.is_some())
+            .collect::<Vec<_>>();
+        for (b, branch) in branches.iter().enumerate().rev() {
+            if let (true, Some((parent, _))) = (active[b], branch.parent) {
+                active[parent] = true;
+            }
+        }
+
+        // sum and count of the positions requested for each joint of each branch by its children
+        let mut requests = poses
+            .iter()
+            .map(|p| vec![(Vec2::ZERO, 0); p.positions.len()])
+            .collect::<Vec<_>>();
+
+        for iteration in 0..iterations {
+            // early break if every effector with a target is within epsilon of it
+            let solved = branches.iter().zip(poses.iter()).all(|(branch, pose)| {
+                branch.target.is_none_or(|target| {
+                    pose.positions.last().unwrap().distance_squared(target) < epsilon * epsilon
+                })
+            });
+            if solved {
+                return iteration;
+            }
+
+            // backward, from the leaves to the roots
+            for (b, branch) in branches.iter().enumerate().rev() {
+                if !active[b] {
+                    continue;
+                }
+
+                let positions = &mut poses[b].positions;
+                let last = positions.len() - 1;
+
+                // the effector goes to its target and to the average of its children requests
+                let (sum, count) = requests[b][last];
+                positions[last] = match branch.target {
+                    Some(target) => (sum + target) / (count + 1) as f32,
+                    None if count > 0 => sum / count as f32,
+                    None => positions[last],
+                };
+
+                for i in (1..=last).rev() {
+                    // sub-bases are averaged between this branch and their children
+                    let (sum, count) = requests[b][i];
+                    if i != last && count > 0 {
+                        positions[i] = (sum + positions[i]) / (count + 1) as f32;
+                    }
+                    Self::pull(&branch.chain, positions, i);
+                }
+
+                if let Some((parent, joint)) = branch.parent {
+                    let (sum, count) = &mut requests[parent][joint];
+                    *sum += positions[0];
+                    *count += 1;
+                }
+            }
+
+            for request in requests.iter_mut().flatten() {
+                *request = (Vec2::ZERO, 0);
+            }
+
+            // forward, from the roots to the leaves
+            for (b, branch) in branches.iter().enumerate() {
+                let anchor_dir = match branch.parent {
+                    Some((parent, joint)) => {
+                        poses[b].positions[0] = poses[parent].positions[joint];
+
+                        // the anchor of a branch follows the rotation of its sub-base
+                        let offset =
+                            poses[parent].angles[joint] - branches[parent].chain.rest_angles[joint];
+                        Vec2::from_angle(branch.chain.rest_angles[0] + offset)
+                    }
+                    None => {
+                        poses[b].positions[0] = roots[b];
+                        branch.chain.anchor_dir()
+                    }
+                };
+
+                let pose = &mut poses[b];
+                let target = branch.target.unwrap_or(*pose.positions.last().unwrap());
+                Self::forward(&branch.chain, pose, anchor_dir, target);
+            }
+        }
+
+        iterations
+    }
+}
+
+impl IKSolver for FabrikSolver {
+    fn solve(&self, chain: &IKChain, pose: &mut IKPose, target: Vec2) -> usize {
+        chain.float_anchor(pose, target);
+
+        // start from the side of the pole, the iterations keep it
+        chain.bend_to_pole(&mut pose.positions);
+
+        for iteration in 0..chain.iterations {
+            // early break if both effector constraints are within epsilons
+            // or if there are no constrains
+            if chain.solved(&pose.positions, target) {
+                return iteration;
+            }
+
+            Self::solve_iteration(chain, pose, target);
+        }
+
+        chain.iterations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::tests::{bone_lengths, chain};
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    /// a vertical spine, and two arms starting at its top
+    fn tree_poses() -> Vec<IKPose> {
+        let spine = IKPose::from_positions(vec![Vec2::ZERO, Vec2::Y * 10., Vec2::Y * 20.]);
+        let arm = |side: f32| {
+            IKPose::from_positions(vec![
+                Vec2::Y * 20.,
+                Vec2::new(side * 10., 20.),
+                Vec2::new(side * 20., 20.),
+            ])
+        };
+        vec![spine, arm(-1.), arm(1.)]
+    }
+
+    #[test]
+    fn tree_averages_sub_bases() {
+        let lengths = [10., 10.];
+        let spine = chain(&lengths, &[FRAC_PI_2; 3], &[None; 3]);
+        let left = chain(&lengths, &[PI; 3], &[None; 3]);
+        let right = chain(&lengths, &[0.; 3], &[None; 3]);
+
+        // both arms pull the top of the spine towards their side, it ends up in between
+        let branches = [
+            IKBranch {
+                chain: spine,
+                target: None,
+                parent: None,
+            },
+            IKBranch {
+                chain: left,
+                target: Some(Vec2::new(-25., 20.)),
+                parent: Some((0, 2)),
+            },
+            IKBranch {
+                chain: right,
+                target: Some(Vec2::new(25., 20.)),
+                parent: Some((0, 2)),
+            },
+        ];
+        let mut poses = tree_poses();
+        FabrikSolver.solve_tree(&branches, &mut poses, 50, 0.01);
+
+        let sub_base = poses[0].positions[2];
+        assert!(sub_base.distance(Vec2::Y * 20.) < 1e-2);
+        assert_eq!(poses[1].positions[0], sub_base);
+        assert_eq!(poses[2].positions[0], sub_base);
+        assert_eq!(poses[0].positions[0], Vec2::ZERO);
+
+        // a single arm drags it to its side
+        let mut branches = branches;
+        branches[1].target = None;
+        let mut poses = tree_poses();
+        FabrikSolver.solve_tree(&branches, &mut poses, 50, 0.01);
+
+        assert!(poses[0].positions[2].x > 1.);
+        assert!(poses[2].positions[2].distance(Vec2::new(25., 20.)) < 0.01);
+        for pose in &poses {
+            for length in bone_lengths(pose) {
+                assert!((length - 10.).abs() < 1e-3);
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+use std::f32::consts::PI;
+
+use super::IKSolver;
+use crate::{IKChain, IKPose, JointConstraint};
+
+/// damped least squares solver
+///
+/// moves the joint angles along the damped pseudo inverse of the chain's jacobian
+/// it needs more iterations than `FabrikSolver`, but it stays smooth when the target is out of reach
+/// or when the chain is close to being fully extended
+///
+/// the orientation of the last bone towards the effector's target angle is solved together with the position
+#[derive(Clone, Copy, Debug, Reflect)]
+pub struct JacobianSolver {
+    /// damping factor, in world units
+    /// higher values are smoother but slower to converge
+    /// it should be in the same order of magnitude as the bones lengths
+    pub damping: f32,
+}
+
+impl Default for JacobianSolver {
+    fn default() -> Self {
+        Self::new(10.0)
+    }
+}
+
+impl JacobianSolver {
+    pub fn new(damping: f32) -> Self {
+        Self { damping }
+    }
+
+    /// absolute positions of the joints from the angles of the joints relative to the previous bone
+    fn forward(anchor_dir: Vec2, lengths: &[f32], angles: &[f32], positions: &mut [Vec2]) {
+        let mut dir = anchor_dir;
+        for i in 0..lengths.len() {
+            dir = Vec2::from_angle(angles[i]).rotate(dir);
+            positions[i + 1] = positions[i] + dir * lengths[i];
+        }
+    }
+}
+
+impl IKSolver for JacobianSolver {
+    fn solve(&self, chain: &IKChain, pose: &mut IKPose, target: Vec2) -> usize {
+        chain.float_anchor(pose, target);
+
+        let anchor_dir = chain.anchor_dir();
+        let lengths = chain.lengths;
+        let positions = &mut pose.positions;
+        let last = positions.len() - 1;
+
+        chain.bend_to_pole(positions);
+
+        let limits = chain.constraints[..last]
+            .iter()
+            .map(|c| match *c {
+                Some(JointConstraint { ccw, cw }) => (-cw, ccw),
+                None => (-PI, PI),
+            })
+            .collect::<Vec<_>>();
+
+        // angle of each joint relative to the previous bone
+        let mut prev_dir = anchor_dir;
+        let mut angles = positions
+            .windows(2)
+            .zip(&limits)
+            .map(|(w, &(min, max))| {
+                let angle = prev_dir.angle_to(w[1] - w[0]).clamp(min, max);
+                prev_dir = Vec2::from_angle(angle).rotate(prev_dir);
+                angle
+            })
+            .collect::<Vec<_>>();
+
+        Self::forward(anchor_dir, lengths, &angles, positions);
+
+        let damping = Mat3::from_diagonal(Vec3::splat(self.damping * self.damping));
+
+        // the orientation of the last bone is a third row of the jacobian, scaled to world units by its length
+        // every joint rotates it by the same amount
+        let arm = match chain.target_angle {
+            Some(_) => lengths[last - 1],
+            None => 0.,
+        };
+
+        let mut iterations = 0;
+        while iterations < chain.iterations {
+            // early break if the effector is within epsilon of the target
+            if chain.solved(positions, target) {
+                break;
+            }
+            iterations += 1;
+
+            let bone = (positions[last] - positions[last - 1]).normalize();
+            let error = (target - positions[last])
+                .extend(bone.angle_to(chain.oriented_bone_dir(bone)) * arm);
+
+            // each column of the jacobian is the velocity of the effector when rotating a joint
+            // which is perpendicular to the joint -> effector vector
+            let columns = positions[..last]
+                .iter()
+                .map(|&p| (positions[last] - p).perp().extend(arm))
+                .collect::<Vec<_>>();
+
+            // J * J^T + damping^2 * I
+            let jjt = columns.iter().fold(damping, |acc, c| {
+                acc + Mat3::from_cols(*c * c.x, *c * c.y, *c * c.z)
+            });
+
+            // delta = J^T * (J * J^T + damping^2 * I)^-1 * error
+            let f = jjt.inverse() * error;
+            if !f.is_finite() {
+                break;
+            }
+
+            for (i, c) in columns.iter().enumerate() {
+                let (min, max) = limits[i];
+                angles[i] = (angles[i] + c.dot(f)).clamp(min, max);
+            }
+
+            Self::forward(anchor_dir, lengths, &angles, positions);
+        }
+
+        let positions = &pose.positions;
+
+        for i in 0..last {
+            pose.angles[i] = (positions[i + 1] - positions[i]).to_angle();
+        }
+
+        let prev_dir = Vec2::from_angle(pose.angles[last - 1]);
+        pose.angles[last] = chain
+            .effector_dir(prev_dir, positions[last], target)
+            .to_angle();
+
+        iterations
+    }
+}
@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+
+use super::{FabrikSolver, IKSolver};
+use crate::{IKChain, IKPose};
+
+/// side towards which the middle joint of a two bone chain bends
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum BendDirection {
+    /// the middle joint is on the right of the anchor -> target line
+    #[default]
+    Clockwise,
+    /// the middle joint is on the left of the anchor -> target line
+    CounterClockwise,
+}
+
+impl BendDirection {
+    fn sign(self) -> f32 {
+        match self {
+            BendDirection::Clockwise => -1.,
+            BendDirection::CounterClockwise => 1.,
+        }
+    }
+
+    /// bend towards the side of `pole` relative to the `from` -> `to` line
+    fn towards(from: Vec2, to: Vec2, pole: Vec2) -> Option<Self> {
+        let side = (to - from).perp_dot(pole - from);
+        match side {
+            s if s > 0. => Some(BendDirection::CounterClockwise),
+            s if s < 0. => Some(BendDirection::Clockwise),
+            _ => None,
+        }
+    }
+
+    fn flip(self) -> Self {
+        match self {
+            BendDirection::Clockwise => BendDirection::CounterClockwise,
+            BendDirection::CounterClockwise => BendDirection::Clockwise,
+        }
+    }
+}
+
+/// closed form solver for chains of exactly 3 entities (anchor, joint, effector)
+/// solved with the law of cosines, without iterations
+///
+/// the preferred bend direction (or the side of the chain's pole, if any) is used
+/// unless it breaks a joint constraint that the other one satisfies
+/// the effector's target angle only rotates the effector, the bones are fully determined by the target position
+/// chains of any other length are solved with `FabrikSolver`
+#[derive(Clone, Copy, Debug, Default, Reflect)]
+pub struct TwoBoneSolver {
+    /// preferred side of the middle joint
+    pub bend: BendDirection,
+}
+
+impl TwoBoneSolver {
+    pub fn new(bend: BendDirection) -> Self {
+        Self { bend }
+    }
+
+    /// absolute directions of both bones for a given bend
+    /// also returns wether a joint constraint had to be applied
+    fn pose(
+        chain: &IKChain,
+        bend: BendDirection,
+        anchor: Vec2,
+        anchor_dir: Vec2,
+        lengths: [f32; 2],
+        target: Vec2,
+    ) -> ([Vec2; 2], bool) {
+        let [a, b] = lengths;
+        let to_target = target - anchor;
+        let dist = to_target
+            .length()
+            .clamp((a - b).abs(), a + b)
+            .max(f32::EPSILON);
+        let target_dir = to_target.try_normalize().unwrap_or(anchor_dir);
+
+        // angle between the first bone and the anchor -> target line
+        let cos = ((a * a + dist * dist - b * b) / (2. * a * dist)).clamp(-1., 1.);
+        let wanted = Vec2::from_angle(bend.sign() * cos.acos()).rotate(target_dir);
+
+        let angle = anchor_dir.angle_to(wanted);
+        let clamped = chain.constrain(0, angle);
+        let dir0 = Vec2::from_angle(clamped).rotate(anchor_dir);
+
+        let joint = anchor + dir0 * a;
+        let wanted = (target - joint).try_normalize().unwrap_or(dir0);
+        let angle1 = dir0.angle_to(wanted);
+        let clamped1 = chain.constrain(1, angle1);
+        let dir1 = Vec2::from_angle(clamped1).rotate(dir0);
+
+        ([dir0, dir1], clamped != angle || clamped1 != angle1)
+    }
+}
+
+impl IKSolver for TwoBoneSolver {
+    fn solve(&self, chain: &IKChain, pose: &mut IKPose, target: Vec2) -> usize {
+        if chain.len() != 3 {
+            return FabrikSolver.solve(chain, pose, target);
+        }
+
+        chain.float_anchor(pose, target);
+
+        let anchor_dir = chain.anchor_dir();
+        let anchor = pose.positions[0];
+        let lengths = [chain.lengths[0], chain.lengths[1]];
+
+        let bend = chain
+            .pole
+            .and_then(|pole| BendDirection::towards(anchor, target, pole))
+            .unwrap_or(self.bend);
+
+        let end = |[dir0, dir1]: [Vec2; 2]| anchor + dir0 * lengths[0] + dir1 * lengths[1];
+
+        let (mut dirs, constrained) = Self::pose(chain, bend, anchor, anchor_dir, lengths, target);
+
+        // the preferred bend broke a constraint, use the other one if it gets closer
+        if constrained {
+            let (other, _) = Self::pose(chain, bend.flip(), anchor, anchor_dir, lengths, target);
+            if end(other).distance_squared(target) < end(dirs).distance_squared(target) {
+                dirs = other;
+            }
+        }
+
+        let [dir0, dir1] = dirs;
+        let joint = anchor + dir0 * lengths[0];
+        let effector = joint + dir1 * lengths[1];
+        let dir2 = chain.effector_dir(dir1, effector, target);
+
+        pose.positions[1] = joint;
+        pose.positions[2] = effector;
+        pose.angles[0] = dir0.to_angle();
+        pose.angles[1] = dir1.to_angle();
+        pose.angles[2] = dir2.to_angle();
+
+        // closed form, a single step
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::tests::{chain, straight};
+
+    /// middle joint of a chain solved towards a target in front of it
+    fn joint(chain: &IKChain, bend: BendDirection) -> Vec2 {
+        let pose = chain.solve(
+            &TwoBoneSolver::new(bend),
+            straight(chain.lengths),
+            Vec2::X * 15.,
+        );
+        pose.positions[1]
+    }
+
+    #[test]
+    fn bends_towards_side() {
+        let chain = chain(&[10., 10.], &[0.; 3], &[None; 3]);
+
+        // clockwise puts the joint on the right of the anchor -> target line
+        assert!(joint(&chain, BendDirection::Clockwise).y < 0.);
+        assert!(joint(&chain, BendDirection::CounterClockwise).y > 0.);
+    }
+
+    #[test]
+    fn bends_towards_pole() {
+        let mut chain = chain(&[10., 10.], &[0.; 3], &[None; 3]);
+
+        // the pole wins over the preferred bend
+        chain.pole = Some(Vec2::new(5., 10.));
+        assert!(joint(&chain, BendDirection::Clockwise).y > 0.);
+        chain.pole = Some(Vec2::new(5., -10.));
+        assert!(joint(&chain, BendDirection::CounterClockwise).y < 0.);
+
+        // a pole on the line doesn't tell any side
+        chain.pole = Some(Vec2::new(30., 0.));
+        assert!(joint(&chain, BendDirection::CounterClockwise).y > 0.);
+    }
+}
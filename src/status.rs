@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+
+use crate::{IKChain, IKPose};
+
+/// result of the last solve of a chain
+/// added with every `IKConstraint`, and updated by `solve_ik` each time the chain is solved
+///
+/// chains without target, with a weight of 0, or that failed keep their previous status
+/// `IKReached`, `IKLost` and `IKUnreachable` are triggered when it changes
+#[derive(Component, Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct IKStatus {
+    /// number of iterations used by the solver
+    /// less than `IKConstraint::iterations` if the chain was solved early
+    pub iterations: usize,
+
+    /// distance between the effector and the target after solving (and blending)
+    pub distance: f32,
+
+    /// wether the effector is within `epsilon` of the target
+    pub reached: bool,
+
+    /// wether the target was within reach of the anchor, see `IKReach`
+    pub reachable: bool,
+
+    /// wether a joint is at a limit of its `JointConstraint`
+    pub constrained: bool,
+}
+
+impl Default for IKStatus {
+    /// status of a chain that wasn't solved yet
+    fn default() -> Self {
+        Self {
+            iterations: 0,
+            distance: f32::INFINITY,
+            reached: false,
+            reachable: true,
+            constrained: false,
+        }
+    }
+}
+
+impl IKStatus {
+    /// status of `chain` solved to `pose` in `iterations`
+    /// `anchor_dir` is the reference direction of the anchor's constraint
+    pub(crate) fn new(
+        chain: &IKChain,
+        pose: &IKPose,
+        anchor_dir: Vec2,
+        target: Vec2,
+        iterations: usize,
+    ) -> Self {
+        let distance = pose.positions.last().unwrap().distance(target);
+
+        let mut reach = chain.reach(pose.positions[0], anchor_dir);
+        reach.min -= chain.epsilon;
+        reach.max += chain.epsilon;
+
+        Self {
+            iterations,
+            distance,
+            reached: distance < chain.epsilon,
+            reachable: reach.contains(target),
+            constrained: chain.at_limit(pose, anchor_dir),
+        }
+    }
+}
+
+/// replace the status of `entity` by `new`
+/// and trigger the events of what changed
+pub(crate) fn update_status(
+    entity: Entity,
+    new: IKStatus,
+    statuses: &mut Query<&mut IKStatus>,
+    commands: &mut Commands,
+) {
+    let Ok(mut status) = statuses.get_mut(entity) else {
+        return;
+    };
+
+    if new.reached && !status.reached {
+        commands.trigger(IKReached { entity });
+    } else if !new.reached && status.reached {
+        commands.trigger(IKLost { entity });
+    }
+
+    if !new.reachable && status.reachable {
+        commands.trigger(IKUnreachable { entity });
+    }
+
+    *status = new;
+}
+
+/// triggered on the entity holding an `IKConstraint` when its effector gets within `epsilon` of the target
+#[derive(EntityEvent, Clone, Copy, Debug)]
+pub struct IKReached {
+    /// entity holding the `IKConstraint`
+    pub entity: Entity,
+}
+
+/// triggered on the entity holding an `IKConstraint` when its effector leaves the target it had reached
+#[derive(EntityEvent, Clone, Copy, Debug)]
+pub struct IKLost {
+    /// entity holding the `IKConstraint`
+    pub entity: Entity,
+}
+
+/// triggered on the entity holding an `IKConstraint` when its target gets out of reach
+/// (see `IKStatus::reachable`)
+#[derive(EntityEvent, Clone, Copy, Debug)]
+pub struct IKUnreachable {
+    /// entity holding the `IKConstraint`
+    pub entity: Entity,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IKConstraint, IKPlugin, IKTarget};
+
+    /// number of times each event was triggered
+    #[derive(Resource, Default, Debug, PartialEq)]
+    struct Counts {
+        reached: usize,
+        lost: usize,
+        unreachable: usize,
+    }
+
+    #[test]
+    fn events_fire_once_per_change() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            IKPlugin::default().with_debug(false),
+        ))
+        .init_resource::<Counts>()
+        .add_observer(|_: On<IKReached>, mut counts: ResMut<Counts>| counts.reached += 1)
+        .add_observer(|_: On<IKLost>, mut counts: ResMut<Counts>| counts.lost += 1)
+        .add_observer(|_: On<IKUnreachable>, mut counts: ResMut<Counts>| counts.unreachable += 1);
+
+        let chain = [0., 10., 20.]
+            .map(|x| app.world_mut().spawn(Transform::from_xyz(x, 0., 0.)).id())
+            .to_vec();
+        let effector = chain[2];
+        app.world_mut()
+            .entity_mut(effector)
+            .insert(IKConstraint::new(chain).with_target(IKTarget::Pos(Vec2::new(12., 9.))));
+
+        let run = |app: &mut App, target: Vec2, expected: Counts| {
+            let mut constraint = app.world_mut().get_mut::<IKConstraint>(effector).unwrap();
+            constraint.set_target(IKTarget::Pos(target));
+            for _ in 0..5 {
+                app.update();
+            }
+            assert_eq!(app.world().resource::<Counts>(), &expected);
+        };
+
+        let counts = |reached, lost, unreachable| Counts {
+            reached,
+            lost,
+            unreachable,
+        };
+        run(&mut app, Vec2::new(12., 9.), counts(1, 0, 0));
+        run(&mut app, Vec2::new(100., 0.), counts(1, 1, 1));
+        run(&mut app, Vec2::new(9., 12.), counts(2, 1, 1));
+    }
+}